@@ -1,22 +1,112 @@
 use std::io::{BufRead,BufReader,Cursor,Read};
 
-type Spreadsheet = Vec<Vec<i32>>;
-
-fn parse<R: Read>(r: R) -> Spreadsheet {
-    let r = BufReader::new(r);
-    r.lines()
-        .map(|s| s.unwrap()
-             .split_whitespace()
-             .map(|s| s.parse::<i32>().unwrap())
-             .collect() )
-        .collect()
+type Spreadsheet = Vec<Vec<i64>>;
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+enum Layout { Whitespace, Csv, Tsv }
+
+impl Layout {
+    fn detect(input: &str) -> Layout {
+        if input.contains('\t') {
+            Layout::Tsv
+        } else if input.contains(',') {
+            Layout::Csv
+        } else {
+            Layout::Whitespace
+        }
+    }
+
+    fn cells<'a>(&self, line: &'a str) -> Vec<&'a str> {
+        match *self {
+            Layout::Whitespace  => line.split_whitespace().collect(),
+            Layout::Csv         => line.split(',').map(|c| c.trim()).collect(),
+            Layout::Tsv         => line.split('\t').map(|c| c.trim()).collect(),
+        }
+    }
+}
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+enum Empty { Skip, Reject }
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+struct Format {
+    layout: Layout,
+    empty_rows: Empty,
+    empty_cells: Empty,
+}
+
+impl Format {
+    fn new(layout: Layout) -> Format {
+        Format{layout, empty_rows: Empty::Skip, empty_cells: Empty::Skip}
+    }
+}
+
+// Rows and columns are 1-based, as an editor would show them.
+#[derive(PartialEq,Debug)]
+enum ParseError {
+    Io(String),
+    EmptyRow{row: usize},
+    EmptyCell{row: usize, col: usize},
+    BadCell{row: usize, col: usize, text: String},
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            ParseError::Io(ref e)       => write!(f, "read error: {}", e),
+            ParseError::EmptyRow{row}   => write!(f, "row {}: empty row", row),
+            ParseError::EmptyCell{row, col} =>
+                write!(f, "row {}, column {}: empty cell", row, col),
+            ParseError::BadCell{row, col, ref text} =>
+                write!(f, "row {}, column {}: '{}' is not a 64-bit integer", row, col, text),
+        }
+    }
+}
+
+fn parse_row(line: &str, row: usize, format: &Format) -> Result<Vec<i64>, ParseError> {
+    let mut cells = vec![];
+    if line.trim().is_empty() {
+        return Ok(cells)
+    }
+    for (i, text) in format.layout.cells(line).into_iter().enumerate() {
+        let col = i + 1;
+        if text.is_empty() {
+            if format.empty_cells == Empty::Reject {
+                return Err(ParseError::EmptyCell{row, col})
+            }
+            continue
+        }
+        match text.parse::<i64>() {
+            Ok(v)   => cells.push(v),
+            Err(_)  => return Err(ParseError::BadCell{row, col, text: text.to_owned()}),
+        }
+    }
+    Ok(cells)
 }
 
-fn row_checksum_a<T: AsRef<[i32]>>(r: T) -> i32 {
+fn parse<R: Read>(r: R, format: &Format) -> Result<Spreadsheet, ParseError> {
+    let mut spreadsheet = vec![];
+    for (i, line) in BufReader::new(r).lines().enumerate() {
+        let row = i + 1;
+        let line = line.map_err(|e| ParseError::Io(e.to_string()))?;
+        let cells = parse_row(&line, row, format)?;
+        if cells.is_empty() {
+            if format.empty_rows == Empty::Reject {
+                return Err(ParseError::EmptyRow{row})
+            }
+            continue
+        }
+        spreadsheet.push(cells);
+    }
+    Ok(spreadsheet)
+}
+
+fn row_checksum_a<T: AsRef<[i64]>>(r: T) -> i64 {
     r.as_ref().iter().max().unwrap() - r.as_ref().iter().min().unwrap()
 }
 
-fn row_checksum_b<T: AsRef<[i32]>>(r: T) -> i32 {
+#[cfg(test)]
+fn row_checksum_b<T: AsRef<[i64]>>(r: T) -> i64 {
     for a in r.as_ref().iter() {
         for b in r.as_ref().iter() {
             if a == b {
@@ -32,15 +122,48 @@ fn row_checksum_b<T: AsRef<[i32]>>(r: T) -> i32 {
     unreachable!();
 }
 
-fn checksum<F: FnMut(&Vec<i32>) -> i32>(f: F, s: Spreadsheet) -> i32 {
+// Sorted distinct cells, smallest divisor first. For each candidate divisor
+// `l` we jump through the larger cells a multiple of `l` at a time: the
+// first cell at or past a multiple either is that multiple or tells us the
+// next one worth looking for. Each jump is a binary search, so a divisor
+// costs O(q log n) for the q distinct quotients among the larger cells,
+// however far apart the cells are.
+// Only positive cells take part: zero can't divide anything.
+fn row_checksum_b_fast<T: AsRef<[i64]>>(r: T) -> i64 {
+    let mut cells : Vec<i64> = r.as_ref().iter().cloned().filter(|&c| c > 0).collect();
+    cells.sort();
+    cells.dedup();
+    for (i, &l) in cells.iter().enumerate() {
+        let mut larger = &cells[i+1..];
+        while let Some(&m) = larger.first() {
+            let d = m / l;
+            if d * l == m {
+                return d
+            }
+            let next = match (d + 1).checked_mul(l) {
+                Some(next)  => next,
+                None        => break,
+            };
+            larger = &larger[larger.partition_point(|&c| c < next)..];
+        }
+    }
+    unreachable!();
+}
+
+fn checksum<F: FnMut(&Vec<i64>) -> i64>(f: F, s: Spreadsheet) -> i64 {
     s.iter().map(f).sum()
 }
 
 fn main() {
     let mut buf = String::new();
     std::io::stdin().read_to_string(&mut buf).unwrap();
-    println!("{}", checksum(|r| row_checksum_a(r), parse(Cursor::new(&buf))));
-    println!("{}", checksum(|r| row_checksum_b(r), parse(Cursor::new(&buf))));
+    let format = Format::new(Layout::detect(&buf));
+    let spreadsheet = match parse(Cursor::new(&buf), &format) {
+        Ok(s)   => s,
+        Err(e)  => { eprintln!("{}", e); std::process::exit(1) },
+    };
+    println!("{}", checksum(|r| row_checksum_a(r), spreadsheet.clone()));
+    println!("{}", checksum(|r| row_checksum_b_fast(r), spreadsheet));
 }
 
 #[test]
@@ -48,8 +171,39 @@ fn parse_test() {
     let input = "5 1 9 5
     7 5 3
     2 4 6 8";
-    let spreadsheet = parse(std::io::Cursor::new(input));
-    assert_eq!(vec![vec![5,1,9,5], vec![7,5,3], vec![2,4,6,8]], spreadsheet);
+    let spreadsheet = parse(std::io::Cursor::new(input), &Format::new(Layout::Whitespace));
+    assert_eq!(Ok(vec![vec![5,1,9,5], vec![7,5,3], vec![2,4,6,8]]), spreadsheet);
+}
+
+#[test]
+fn parse_layouts_test() {
+    let expected = Ok(vec![vec![5,1,9,5], vec![7,5,3], vec![2,4,6,8]]);
+    let csv = "5, 1,9,5\n\n7,5,3\n2,4,6,8\n";
+    assert_eq!(Layout::Csv, Layout::detect(csv));
+    assert_eq!(expected, parse(Cursor::new(csv), &Format::new(Layout::Csv)));
+    let tsv = "5\t1\t9\t5\n7\t5\t3\n2\t4\t6\t8";
+    assert_eq!(Layout::Tsv, Layout::detect(tsv));
+    assert_eq!(expected, parse(Cursor::new(tsv), &Format::new(Layout::Tsv)));
+    assert_eq!(Ok(vec![vec![9000000000, 3000000000]]),
+               parse(Cursor::new("9000000000 3000000000"), &Format::new(Layout::Whitespace)));
+}
+
+#[test]
+fn parse_empty_test() {
+    let input = "1,2\n\n3,,4";
+    let skip = Format::new(Layout::Csv);
+    assert_eq!(Ok(vec![vec![1,2], vec![3,4]]), parse(Cursor::new(input), &skip));
+    let rows = Format{empty_rows: Empty::Reject, ..skip};
+    assert_eq!(Err(ParseError::EmptyRow{row: 2}), parse(Cursor::new(input), &rows));
+    let cells = Format{empty_cells: Empty::Reject, ..skip};
+    assert_eq!(Err(ParseError::EmptyCell{row: 3, col: 2}), parse(Cursor::new(input), &cells));
+}
+
+#[test]
+fn parse_error_test() {
+    let r = parse(Cursor::new("1 2\n3 x4"), &Format::new(Layout::Whitespace));
+    assert_eq!(Err(ParseError::BadCell{row: 2, col: 2, text: "x4".to_owned()}), r);
+    assert_eq!("row 2, column 2: 'x4' is not a 64-bit integer", r.unwrap_err().to_string());
 }
 
 #[test]
@@ -64,3 +218,102 @@ fn row_checksum_b_test() {
     assert_eq!(3, row_checksum_b(&vec![9, 4, 7, 3]));
     assert_eq!(2, row_checksum_b(&vec![3, 8, 6, 5]));
 }
+
+#[test]
+fn row_checksum_b_fast_test() {
+    assert_eq!(4, row_checksum_b_fast(vec![5, 9, 2, 8]));
+    assert_eq!(3, row_checksum_b_fast(vec![9, 4, 7, 3]));
+    assert_eq!(2, row_checksum_b_fast(vec![3, 8, 6, 5]));
+    assert_eq!(7, row_checksum_b_fast(vec![1, 7]));
+    assert_eq!(5, row_checksum_b_fast(vec![4, 4, 20, 9]));
+    assert_eq!(3, row_checksum_b_fast(vec![0, 5, 7, 21]));
+    // Probing past 1 << 61 would run off the end of i64.
+    let row = vec![1 << 61, 3_000_000_000_000_000_000, 6_000_000_000_000_000_000, i64::MAX - 2, i64::MAX - 1];
+    assert_eq!(2, row_checksum_b_fast(row));
+}
+
+// Cells drawn from [n, 2n) never divide each other, so planting 2*l with
+// l < 1.5n gives a row with exactly one evenly divisible pair. Striding by
+// a prime scatters the cells.
+#[cfg(test)]
+fn wide_row(seed: u64, n: i64, len: usize) -> Vec<i64> {
+    let seed = seed as i64;
+    let mut row : Vec<i64> = (0..len as i64).map(|i| n + (i * 7919 + seed * 104729) % n).collect();
+    let l = n + (seed * 7907) % (n / 2);
+    let at = (seed as usize * 31) % len;
+    row[at] = l;
+    row.push(2 * l);
+    row
+}
+
+// Every row of up to four distinct cells from 1..=n, in both orders, that
+// has exactly one evenly divisible pair.
+#[cfg(test)]
+fn small_rows(n: i64) -> Vec<Vec<i64>> {
+    let mut rows: Vec<Vec<i64>> = vec![vec![]];
+    for c in 1..n + 1 {
+        let longer: Vec<Vec<i64>> = rows.iter()
+            .filter(|r| r.len() < 4)
+            .map(|r| { let mut r = r.clone(); r.push(c); r })
+            .collect();
+        rows.extend(longer);
+    }
+    let pairs = |r: &Vec<i64>| (0..r.len()).flat_map(|i| (i+1..r.len()).map(move |j| (i, j)))
+        .filter(|&(i, j)| r[j] % r[i] == 0)
+        .count();
+    rows.into_iter()
+        .filter(|r| pairs(r) == 1)
+        .flat_map(|r| { let mut rev = r.clone(); rev.reverse(); vec![r, rev] })
+        .collect()
+}
+
+#[test]
+fn row_checksum_b_fast_matches_quadratic_test() {
+    for row in small_rows(24) {
+        assert_eq!(row_checksum_b(&row), row_checksum_b_fast(&row), "{:?}", row);
+    }
+}
+
+#[test]
+fn row_checksum_b_fast_wide_row_test() {
+    let row = wide_row(7, 1_000_000, 200_000);
+    assert_eq!(2, row_checksum_b_fast(&row));
+}
+
+// The first `count` primes from `from` on.
+#[cfg(test)]
+fn primes_from(from: usize, count: usize) -> Vec<i64> {
+    let mut sieve = vec![true; from * 2];
+    let mut primes = vec![];
+    for i in 2..sieve.len() {
+        if !sieve[i] {
+            continue
+        }
+        if i >= from {
+            primes.push(i as i64);
+            if primes.len() == count {
+                break
+            }
+        }
+        for m in (i * i..sieve.len()).step_by(i) {
+            sieve[m] = false;
+        }
+    }
+    primes
+}
+
+// Primes never divide each other, so 2*p is the only divisible pair, and
+// one huge cell leaves every divisor a long way to go to reach it.
+#[test]
+fn row_checksum_b_fast_outlier_test() {
+    let mut row = primes_from(1000, 300);
+    row.push(2 * row[123]);
+    row.push(999_999_999_989);
+    row.reverse();
+    assert_eq!(row_checksum_b(&row), row_checksum_b_fast(&row));
+
+    let mut row = primes_from(1_000_000, 60_000);
+    row.insert(31_337, 2 * row[42_000]);
+    row.push(999_999_999_989);
+    assert_eq!(2, row_checksum_b_fast(&row));
+}
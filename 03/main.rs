@@ -1,4 +1,6 @@
-use std::collections::HashMap;
+use std::collections::{HashMap,HashSet};
+use std::fmt::Write;
+use std::hash::Hash;
 /*
 
 17  16  15  14  13
@@ -9,13 +11,15 @@ use std::collections::HashMap;
 
 */
 
+#[cfg(test)]
 #[derive(Eq,PartialEq,Debug)]
 struct CircleSize {
-    total_count: i32,
-    side_size: i32,
+    total_count: i64,
+    side_size: i64,
 }
 
-fn inner_size(circles: i32) -> CircleSize {
+#[cfg(test)]
+fn inner_size(circles: i64) -> CircleSize {
     if circles == 0 {
         return CircleSize{total_count: 1, side_size: 1}
     }
@@ -27,34 +31,18 @@ fn inner_size(circles: i32) -> CircleSize {
 
 #[derive(Clone,Copy,PartialEq,Debug)]
 struct Position {
-    x: i32,
-    y: i32,
-    side: i32,
+    x: i64,
+    y: i64,
+    side: i64,
 }
 
 impl Position {
-    fn new(x: i32, y: i32, side: i32) -> Position {
+    #[cfg(test)]
+    fn new(x: i64, y: i64, side: i64) -> Position {
         Position{x, y, side}
     }
 
-    fn distance(&self) -> i32 {
-        self.x.abs() + self.y.abs()
-    }
-
-    fn neighbours(&self) -> Vec<Position> {
-        let mut ret = vec![];
-        for xo in vec![-1,0,1] {
-            for yo in vec![-1, 0, 1] {
-                if xo == 0 && yo == 0 {
-                    continue
-                }
-                ret.push(Position{x: self.x + xo, y: self.y + yo, side: self.side})
-            }
-        }
-        debug_assert!(ret.len() == 8);
-        ret
-    }
-
+    #[cfg(test)]
     fn next(&self) -> Position {
         let offset = self.side / 2;
         if self.x == offset {
@@ -74,7 +62,8 @@ impl Position {
     }
 }
 
-fn number_to_position(input: i32) -> Position {
+#[cfg(test)]
+fn number_to_position_walk(input: i64) -> Position {
     if input == 1 {
         return Position{x: 0, y: 0, side: 1}
     }
@@ -100,45 +89,451 @@ fn number_to_position(input: i32) -> Position {
     pos
 }
 
-fn solve(input: i32) -> i32 {
-    number_to_position(input).distance()
+fn isqrt(n: u64) -> u64 {
+    let mut r = (n as f64).sqrt() as u64;
+    while r as u128 * r as u128 > n as u128 {
+        r -= 1;
+    }
+    while (r as u128 + 1) * (r as u128 + 1) <= n as u128 {
+        r += 1;
+    }
+    r
+}
+
+// Ring k holds the squares ((2k-1)^2, (2k+1)^2] and ends in its bottom right
+// corner, so the ring and the side within it follow from a square root.
+// The outermost rings reach past i64 squared, hence the i128 arithmetic.
+fn number_to_position(input: u64) -> Position {
+    debug_assert!(input > 0);
+    let k = isqrt(input - 1).div_ceil(2) as i128;
+    let side = 2 * k + 1;
+    if k == 0 {
+        return Position{x: 0, y: 0, side: 1}
+    }
+    let n = input as i128;
+    let t = 2 * k;
+    let m = side * side;
+    let (x, y) = if n >= m - t {
+        (k - (m - n), -k)
+    } else if n >= m - 2 * t {
+        (-k, -k + (m - t - n))
+    } else if n >= m - 3 * t {
+        (-k + (m - 2 * t - n), k)
+    } else {
+        (k, k - (m - 3 * t - n))
+    };
+    Position{x: x as i64, y: y as i64, side: side as i64}
+}
+
+// Squares are numbered up to u64::MAX, which covers every ring out to
+// 2^31 - 1 and part of the one after it.
+fn position_to_number(pos: &Position) -> u64 {
+    let (x, y) = (pos.x as i128, pos.y as i128);
+    let k = x.abs().max(y.abs());
+    let t = 2 * k;
+    let m = (2 * k + 1) * (2 * k + 1);
+    let n = if k == 0 {
+        1
+    } else if y == -k {
+        m - (k - x)
+    } else if x == -k {
+        m - t - (y + k)
+    } else if y == k {
+        m - 2 * t - (x + k)
+    } else {
+        m - 3 * t - (k - y)
+    };
+    assert!(n <= u64::MAX as i128, "({}, {}) is numbered past u64::MAX", pos.x, pos.y);
+    n as u64
+}
+
+fn solve(input: u64) -> i64 {
+    spiral_distance(&SquareSpiral, input)
+}
+
+// Little-endian base 10^9 limbs without leading zeros, just enough
+// arithmetic for the stress test sums.
+#[derive(Clone,PartialEq,Eq,Debug)]
+struct BigUint {
+    limbs: Vec<u32>,
+}
+
+const LIMB: u64 = 1_000_000_000;
+
+impl BigUint {
+    fn add_assign(&mut self, other: &BigUint) {
+        let mut carry = 0;
+        for i in 0..self.limbs.len().max(other.limbs.len()) {
+            if i == self.limbs.len() {
+                self.limbs.push(0);
+            }
+            let sum = self.limbs[i] as u64 + *other.limbs.get(i).unwrap_or(&0) as u64 + carry;
+            self.limbs[i] = (sum % LIMB) as u32;
+            carry = sum / LIMB;
+        }
+        if carry > 0 {
+            self.limbs.push(carry as u32);
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+}
+
+impl From<u64> for BigUint {
+    fn from(mut v: u64) -> BigUint {
+        let mut limbs = vec![];
+        while v > 0 {
+            limbs.push((v % LIMB) as u32);
+            v /= LIMB;
+        }
+        BigUint{limbs}
+    }
 }
 
-struct Grid {
-    data: HashMap<(i32, i32), i32>,
+impl Ord for BigUint {
+    fn cmp(&self, other: &BigUint) -> std::cmp::Ordering {
+        self.limbs.len().cmp(&other.limbs.len())
+            .then_with(|| self.limbs.iter().rev().cmp(other.limbs.iter().rev()))
+    }
 }
 
-impl Grid {
-    fn new() -> Self {
-        Grid{data: HashMap::new()}
+impl PartialOrd for BigUint {
+    fn partial_cmp(&self, other: &BigUint) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
     }
+}
 
-    fn get(&self, pos: Position) -> Option<i32> {
-        self.data.get(&(pos.x, pos.y)).cloned()
+impl std::fmt::Display for BigUint {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.limbs.split_last() {
+            None                => write!(f, "0"),
+            Some((top, rest))   => {
+                write!(f, "{}", top)?;
+                rest.iter().rev().try_for_each(|l| write!(f, "{:09}", l))
+            },
+        }
     }
+}
+
+// The square spiral's neighbour sums, yielded as (square, (x, y), value).
+fn stress_values() -> NeighbourSums<'static, SquareSpiral> {
+    neighbour_sums(&SquareSpiral)
+}
+
+// Squares are numbered from 1, so there is nothing at 0.
+fn stress_value_at(n: u64) -> Option<BigUint> {
+    n.checked_sub(1).and_then(|i| stress_values().nth(i as usize)).map(|(_, _, v)| v)
+}
+
+fn first_stress_above(threshold: &BigUint) -> (u64, Position, BigUint) {
+    let (n, _, v) = stress_values().find(|(_, _, v)| v > threshold).unwrap();
+    (n, number_to_position(n), v)
+}
+
+// Values only grow after square 2, so the search stops once it passes `value`.
+fn stress_value_position(value: &BigUint) -> Option<Position> {
+    stress_values()
+        .take_while(|(n, _, v)| *n <= 2 || v <= value)
+        .find(|(_, _, v)| v == value)
+        .map(|(n, _, _)| number_to_position(n))
+}
+
+fn solve_b(input: u64) -> BigUint {
+    first_stress_above(&BigUint::from(input)).2
+}
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+enum Label { Square, Stress }
 
-    fn set(&mut self, n: i32) -> i32 {
+// Walks x towards the origin first, then y, so the path is one of the
+// shortest ones and always the same for a given square.
+fn manhattan_path(from: &Position) -> Vec<(i64, i64)> {
+    let (mut x, mut y) = (from.x, from.y);
+    let mut path = vec![(x, y)];
+    while x != 0 {
+        x -= x.signum();
+        path.push((x, y));
+    }
+    while y != 0 {
+        y -= y.signum();
+        path.push((x, y));
+    }
+    path
+}
+
+fn render(radius: i64, label: Label, highlight: Option<u64>) -> String {
+    let side = 2 * radius + 1;
+    let stress : HashMap<(i64, i64), BigUint> = match label {
+        Label::Square   => HashMap::new(),
+        Label::Stress   => stress_values().take((side * side) as usize).map(|(_, pos, v)| (pos, v)).collect(),
+    };
+    let path : HashSet<(i64, i64)> = highlight
+        .map(|n| manhattan_path(&number_to_position(n)).into_iter().collect())
+        .unwrap_or_default();
+
+    let mut labels = vec![];
+    for y in (-radius..radius+1).rev() {
+        let row : Vec<String> = (-radius..radius+1)
+            .map(|x| match label {
+                Label::Square   => position_to_number(&Position{x, y, side}).to_string(),
+                Label::Stress   => stress[&(x, y)].to_string(),
+            })
+            .collect();
+        labels.push(row);
+    }
+    let width = labels.iter().flat_map(|r| r.iter()).map(|l| l.len()).max().unwrap();
+
+    let mut out = String::new();
+    for (row, y) in labels.iter().zip((-radius..radius+1).rev()) {
+        let mut line = String::new();
+        for (l, x) in row.iter().zip(-radius..radius+1) {
+            if path.contains(&(x, y)) {
+                write!(line, "[{:>w$}]", l, w = width).unwrap();
+            } else {
+                write!(line, " {:>w$} ", l, w = width).unwrap();
+            }
+        }
+        writeln!(out, "{}", line.trim_end()).unwrap();
+    }
+    out
+}
+
+// A lattice whose cells are numbered outwards from 1 at the origin, one
+// shell at a time, which is all the part A and B style queries rely on.
+// Neighbours are never more than one shell apart.
+trait Spiral {
+    type Pos: Clone + Eq + Hash;
+
+    fn position(&self, n: u64) -> Self::Pos;
+    fn distance(&self, pos: &Self::Pos) -> i64;
+    fn neighbours(&self, pos: &Self::Pos) -> Vec<Self::Pos>;
+    fn shell(&self, pos: &Self::Pos) -> i64;
+}
+
+fn spiral_distance<S: Spiral>(spiral: &S, n: u64) -> i64 {
+    spiral.distance(&spiral.position(n))
+}
+
+// A cell only touches its own shell and the ones either side, so once a
+// new shell starts everything two shells in is dropped.
+struct NeighbourSums<'a, S: Spiral + 'a> {
+    spiral: &'a S,
+    n: u64,
+    shell: i64,
+    values: HashMap<S::Pos, BigUint>,
+}
+
+fn neighbour_sums<S: Spiral>(spiral: &S) -> NeighbourSums<'_, S> {
+    NeighbourSums{spiral, n: 0, shell: 0, values: HashMap::new()}
+}
+
+impl<'a, S: Spiral> Iterator for NeighbourSums<'a, S> {
+    type Item = (u64, S::Pos, BigUint);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.n += 1;
+        let pos = self.spiral.position(self.n);
+        let shell = self.spiral.shell(&pos);
+        if shell > self.shell {
+            self.shell = shell;
+            let spiral = self.spiral;
+            self.values.retain(|p, _| spiral.shell(p) >= shell - 1);
+        }
+        let mut sum = BigUint::from(0);
+        for neighbour in self.spiral.neighbours(&pos) {
+            if let Some(v) = self.values.get(&neighbour) {
+                sum.add_assign(v);
+            }
+        }
+        let sum = if sum.is_zero() { BigUint::from(1) } else { sum };
+        self.values.insert(pos.clone(), sum.clone());
+        Some((self.n, pos, sum))
+    }
+}
+
+fn first_sum_above<S: Spiral>(spiral: &S, threshold: &BigUint) -> BigUint {
+    neighbour_sums(spiral).find(|(_, _, v)| v > threshold).unwrap().2
+}
+
+struct SquareSpiral;
+
+impl Spiral for SquareSpiral {
+    type Pos = (i64, i64);
+
+    fn position(&self, n: u64) -> (i64, i64) {
         let pos = number_to_position(n);
-        let neighbours = pos.neighbours();
-        let sum = neighbours.into_iter().map(|n| self.get(n).unwrap_or(0)).sum();
-        let sum = if sum == 0 { 1 } else { sum };
-        self.data.insert((pos.x, pos.y), sum);
-        sum
+        (pos.x, pos.y)
+    }
+
+    fn distance(&self, &(x, y): &(i64, i64)) -> i64 {
+        x.abs() + y.abs()
+    }
+
+    fn neighbours(&self, &(x, y): &(i64, i64)) -> Vec<(i64, i64)> {
+        let mut ret = vec![];
+        for xo in -1..2 {
+            for yo in -1..2 {
+                if xo != 0 || yo != 0 {
+                    ret.push((x + xo, y + yo));
+                }
+            }
+        }
+        ret
+    }
+
+    fn shell(&self, &(x, y): &(i64, i64)) -> i64 {
+        x.abs().max(y.abs())
     }
 }
 
-fn solve_b(input: i32) -> i32 {
-    let mut g = Grid::new();
-    for i in 1.. {
-        let v = g.set(i);
-        if v > input {
-            return v
+// Same axial coordinates as day 11.
+#[derive(Clone,Debug,PartialEq,Eq,Hash)]
+struct AxialPos { q: i64, r: i64, }
+
+const HEX_N: (i64, i64) = (0, -1);
+const HEX_NE: (i64, i64) = (1, -1);
+const HEX_SE: (i64, i64) = (1, 0);
+const HEX_S: (i64, i64) = (0, 1);
+const HEX_SW: (i64, i64) = (-1, 1);
+const HEX_NW: (i64, i64) = (-1, 0);
+
+// Ring k ends in the corner k steps SE of the origin and starts one step N
+// of it, so consecutive cells are always neighbours, like on the square.
+struct HexSpiral;
+
+impl Spiral for HexSpiral {
+    type Pos = AxialPos;
+
+    fn position(&self, n: u64) -> AxialPos {
+        if n == 1 {
+            return AxialPos{q: 0, r: 0}
+        }
+        let inside = |k: i64| 1 + 3 * k * (k - 1);
+        let mut k = (isqrt((n - 1) / 3) as i64).max(1);
+        while k > 1 && inside(k) >= n as i64 {
+            k -= 1;
+        }
+        while inside(k + 1) < n as i64 {
+            k += 1;
         }
+        let j = n as i64 - inside(k);
+        let side = ((j - 1) / k) as usize;
+        let steps = j - side as i64 * k;
+        let corners = [HEX_SE, HEX_NE, HEX_N, HEX_NW, HEX_SW, HEX_S];
+        let dirs = [HEX_N, HEX_NW, HEX_SW, HEX_S, HEX_SE, HEX_NE];
+        let (cq, cr) = corners[side];
+        let (dq, dr) = dirs[side];
+        AxialPos{q: k * cq + steps * dq, r: k * cr + steps * dr}
+    }
+
+    fn distance(&self, pos: &AxialPos) -> i64 {
+        (pos.q.abs() + pos.r.abs() + (pos.q + pos.r).abs()) / 2
+    }
+
+    fn neighbours(&self, pos: &AxialPos) -> Vec<AxialPos> {
+        [HEX_N, HEX_NE, HEX_SE, HEX_S, HEX_SW, HEX_NW].iter()
+            .map(|&(dq, dr)| AxialPos{q: pos.q + dq, r: pos.r + dr})
+            .collect()
+    }
+
+    fn shell(&self, pos: &AxialPos) -> i64 {
+        self.distance(pos)
     }
-    unreachable!();
+}
+
+#[derive(Clone,Debug,PartialEq,Eq,Hash)]
+struct CubePos { x: i64, y: i64, z: i64, }
+
+// Shell k is the surface of the (2k+1)^3 cube: its bottom face row by row,
+// then each middle layer as a square spiral ring, then its top face.
+struct CubeSpiral;
+
+impl Spiral for CubeSpiral {
+    type Pos = CubePos;
+
+    fn position(&self, n: u64) -> CubePos {
+        if n == 1 {
+            return CubePos{x: 0, y: 0, z: 0}
+        }
+        let mut k = 1;
+        while ((2 * k + 1) * (2 * k + 1) * (2 * k + 1)) < n as i64 {
+            k += 1;
+        }
+        let side = 2 * k + 1;
+        let face = side * side;
+        let ring = 8 * k;
+        let mut i = n as i64 - (2 * k - 1) * (2 * k - 1) * (2 * k - 1) - 1;
+        if i < face {
+            return CubePos{x: -k + i % side, y: -k + i / side, z: -k}
+        }
+        i -= face;
+        if i < (side - 2) * ring {
+            let square = number_to_position(((side - 2) * (side - 2) + 1 + i % ring) as u64);
+            return CubePos{x: square.x, y: square.y, z: -k + 1 + i / ring}
+        }
+        i -= (side - 2) * ring;
+        CubePos{x: -k + i % side, y: -k + i / side, z: k}
+    }
+
+    fn distance(&self, pos: &CubePos) -> i64 {
+        pos.x.abs() + pos.y.abs() + pos.z.abs()
+    }
+
+    fn neighbours(&self, pos: &CubePos) -> Vec<CubePos> {
+        let mut ret = vec![];
+        for xo in -1..2 {
+            for yo in -1..2 {
+                for zo in -1..2 {
+                    if xo == 0 && yo == 0 && zo == 0 {
+                        continue
+                    }
+                    ret.push(CubePos{x: pos.x + xo, y: pos.y + yo, z: pos.z + zo});
+                }
+            }
+        }
+        ret
+    }
+
+    fn shell(&self, pos: &CubePos) -> i64 {
+        pos.x.abs().max(pos.y.abs()).max(pos.z.abs())
+    }
+}
+
+fn solve_on<S: Spiral>(spiral: &S, input: u64) {
+    println!("{}", spiral_distance(spiral, input));
+    println!("{}", first_sum_above(spiral, &BigUint::from(input)));
 }
 
 fn main() {
+    let args : Vec<String> = std::env::args().collect();
+    if args.len() > 2 && args[1] == "render" {
+        let radius = args[2].parse().unwrap();
+        let label = if args.get(3).map(|s| s.as_str()) == Some("stress") { Label::Stress } else { Label::Square };
+        let highlight = args.get(4).map(|s| s.parse().unwrap());
+        print!("{}", render(radius, label, highlight));
+        return
+    }
+    if args.len() > 2 && args[1] == "stress" {
+        match stress_value_at(args[2].parse().unwrap()) {
+            Some(v)     => println!("{}", v),
+            None        => println!("squares are numbered from 1"),
+        }
+        return
+    }
+    if args.len() > 2 && args[1] == "find" {
+        match stress_value_position(&BigUint::from(args[2].parse::<u64>().unwrap())) {
+            Some(pos)   => println!("{} {}", pos.x, pos.y),
+            None        => println!("not a stress value"),
+        }
+        return
+    }
+    if args.len() > 2 && (args[1] == "hex" || args[1] == "cube") {
+        let input = args[2].parse().unwrap();
+        if args[1] == "hex" { solve_on(&HexSpiral, input) } else { solve_on(&CubeSpiral, input) }
+        return
+    }
     println!("{}", solve(361527));
     println!("{}", solve_b(361527));
 }
@@ -155,7 +550,8 @@ fn inner_size_test() {
                inner_size(3));
 }
 
-fn start(circle: i32) -> Position {
+#[cfg(test)]
+fn start(circle: i64) -> Position {
     Position{x: circle, y: 1 - circle, side: 2 * circle + 1}
 }
 
@@ -208,15 +604,228 @@ fn solve_test() {
 
 #[test]
 fn test_grid_set() {
-    let mut g = Grid::new();
-    assert_eq!(1, g.set(1));
-    assert_eq!(1, g.set(2));
-    assert_eq!(2, g.set(3));
-    assert_eq!(4, g.set(4));
-    assert_eq!(5, g.set(5));
-    assert_eq!(10, g.set(6));
-    assert_eq!(11, g.set(7));
-    assert_eq!(23, g.set(8));
-    assert_eq!(25, g.set(9));
-    assert_eq!(26, g.set(10));
+    let mut values = stress_values();
+    let mut set = |n| { let (m, _, v) = values.next().unwrap(); assert_eq!(n, m); v };
+    assert_eq!(BigUint::from(1), set(1));
+    assert_eq!(BigUint::from(1), set(2));
+    assert_eq!(BigUint::from(2), set(3));
+    assert_eq!(BigUint::from(4), set(4));
+    assert_eq!(BigUint::from(5), set(5));
+    assert_eq!(BigUint::from(10), set(6));
+    assert_eq!(BigUint::from(11), set(7));
+    assert_eq!(BigUint::from(23), set(8));
+    assert_eq!(BigUint::from(25), set(9));
+    assert_eq!(BigUint::from(26), set(10));
+}
+
+#[test]
+fn number_to_position_matches_walk_test() {
+    // The walk reports the next ring's side for corner squares, so only the
+    // coordinates are compared.
+    let check = |n: u64| {
+        let walked = number_to_position_walk(n as i64);
+        let pos = number_to_position(n);
+        assert_eq!((walked.x, walked.y), (pos.x, pos.y), "square {}", n);
+    };
+    (1..20_000).for_each(&check);
+}
+
+// The walk above restarts from the ring's first square on every call, so
+// for the first million squares keep one walk going instead.
+#[test]
+fn number_to_position_matches_continued_walk_test() {
+    let mut walked = Position::new(0, 0, 1);
+    for n in 1..1_000_000u64 {
+        if n > 1 {
+            let k = walked.side / 2;
+            walked = if walked.x == k && walked.y == -k { start(k + 1) } else { walked.next() };
+        }
+        let pos = number_to_position(n);
+        assert_eq!((walked.x, walked.y), (pos.x, pos.y), "square {}", n);
+    }
+}
+
+#[test]
+fn position_to_number_test() {
+    let mut prev = number_to_position(1);
+    assert_eq!(1, position_to_number(&prev));
+    for n in 2..1_000_000 {
+        let pos = number_to_position(n);
+        assert_eq!(n, position_to_number(&pos));
+        assert_eq!(1, (pos.x - prev.x).abs() + (pos.y - prev.y).abs());
+        prev = pos;
+    }
+}
+
+#[test]
+fn number_to_position_large_test() {
+    let n = 4_000_000_000_000_000_000;
+    let pos = number_to_position(n);
+    assert_eq!(n, position_to_number(&pos));
+    assert_eq!(n + 1, position_to_number(&number_to_position(n + 1)));
+    assert_eq!(Position::new(1_000_000_000, -1_000_000_000, 2_000_000_001),
+               number_to_position(2_000_000_001 * 2_000_000_001));
+}
+
+#[cfg(test)]
+const MAX_RING: i64 = (1 << 31) - 1;
+
+#[test]
+fn number_to_position_extremes_test() {
+    let last = (2 * MAX_RING as u64 + 1) * (2 * MAX_RING as u64 + 1);
+    assert_eq!(Position::new(MAX_RING, -MAX_RING, 2 * MAX_RING + 1), number_to_position(last));
+    assert_eq!(last, position_to_number(&number_to_position(last)));
+    let pos = number_to_position(u64::MAX);
+    assert_eq!((-MAX_RING + 1, MAX_RING + 1), (pos.x, pos.y));
+    assert_eq!(u64::MAX, position_to_number(&pos));
+    assert_eq!(u64::MAX - 1, position_to_number(&number_to_position(u64::MAX - 1)));
+}
+
+#[test]
+#[should_panic(expected = "numbered past u64::MAX")]
+fn position_to_number_out_of_range_test() {
+    position_to_number(&Position::new(MAX_RING + 1, -MAX_RING - 1, 0));
+}
+
+#[test]
+fn stress_values_test() {
+    let values : Vec<String> = stress_values().take(12).map(|(_, _, v)| v.to_string()).collect();
+    assert_eq!(vec!["1", "1", "2", "4", "5", "10", "11", "23", "25", "26", "54", "57"], values);
+    assert_eq!((23, (0, -2), BigUint::from(806)), stress_values().nth(22).unwrap());
+}
+
+#[test]
+fn stress_queries_test() {
+    assert_eq!(BigUint::from(363010), solve_b(361527));
+    assert_eq!(Some(BigUint::from(806)), stress_value_at(23));
+    assert_eq!(Some(BigUint::from(1)), stress_value_at(1));
+    assert_eq!(None, stress_value_at(0));
+    assert_eq!(Some(Position{x: -1, y: 0, side: 3}), stress_value_position(&BigUint::from(10)));
+    assert_eq!(None, stress_value_position(&BigUint::from(12)));
+    assert_eq!(Some(Position{x: 0, y: 0, side: 1}), stress_value_position(&BigUint::from(1)));
+}
+
+#[test]
+fn stress_values_big_test() {
+    let (n, pos, v) = first_stress_above(&BigUint::from(u64::MAX));
+    assert_eq!(n, position_to_number(&pos));
+    assert!(v > BigUint::from(u64::MAX));
+    let at = stress_value_at(5000).unwrap().to_string();
+    assert_eq!(76, at.len());
+    let mut prev = BigUint::from(0);
+    for (_, _, v) in stress_values().skip(2).take(4998) {
+        assert!(v > prev);
+        prev = v;
+    }
+    assert_eq!(at, prev.to_string());
+}
+
+#[test]
+fn big_uint_test() {
+    let mut a = BigUint::from(999_999_999_999);
+    a.add_assign(&BigUint::from(1));
+    assert_eq!("1000000000000", a.to_string());
+    assert_eq!("0", BigUint::from(0).to_string());
+    assert!(BigUint::from(1_000_000_000) > BigUint::from(999_999_999));
+}
+
+#[test]
+fn manhattan_path_test() {
+    assert_eq!(vec![(0, 0)], manhattan_path(&number_to_position(1)));
+    assert_eq!(vec![(2, 1), (1, 1), (0, 1), (0, 0)], manhattan_path(&number_to_position(12)));
+    assert_eq!(32, manhattan_path(&number_to_position(1024)).len());
+}
+
+#[test]
+fn render_test() {
+    let lines = |l: &[&str]| l.join("\n") + "\n";
+    let expected = lines(&[
+        " 17  16  15  14  13",
+        " 18   5   4   3  12",
+        " 19   6   1   2  11",
+        " 20   7   8   9  10",
+        " 21  22  23  24  25"]);
+    assert_eq!(expected, render(2, Label::Square, None));
+    let expected = lines(&[
+        " 17  16  15  14  13",
+        " 18   5 [ 4][ 3][12]",
+        " 19   6 [ 1]  2  11",
+        " 20   7   8   9  10",
+        " 21  22  23  24  25"]);
+    assert_eq!(expected, render(2, Label::Square, Some(12)));
+    let expected = lines(&[
+        "  5   4   2",
+        " 10 [ 1]  1",
+        " 11  23  25"]);
+    assert_eq!(expected, render(1, Label::Stress, Some(1)));
+}
+
+#[test]
+fn square_spiral_test() {
+    assert_eq!(31, spiral_distance(&SquareSpiral, 1024));
+    assert_eq!(BigUint::from(363010), first_sum_above(&SquareSpiral, &BigUint::from(361527)));
+    assert_sums_keep_every_shell(&SquareSpiral, 2000);
+}
+
+// Same sums as never dropping a shell.
+#[cfg(test)]
+fn assert_sums_keep_every_shell<S: Spiral>(spiral: &S, count: u64) {
+    let mut values = HashMap::new();
+    for (n, pos, v) in neighbour_sums(spiral).take(count as usize) {
+        let mut sum = BigUint::from(0);
+        for neighbour in spiral.neighbours(&pos) {
+            if let Some(v) = values.get(&neighbour) {
+                sum.add_assign(v);
+            }
+        }
+        let sum = if sum.is_zero() { BigUint::from(1) } else { sum };
+        assert_eq!(sum, v, "cell {}", n);
+        values.insert(pos, v);
+    }
+}
+
+// Every shell is fully covered exactly once and each cell sits at the
+// shell's distance from the origin.
+#[cfg(test)]
+fn check_shells<S: Spiral, F: Fn(&S::Pos) -> i64>(spiral: &S, sizes: &[u64], ring_distance: F) {
+    let mut seen = HashSet::new();
+    let mut n = 1;
+    for (k, &size) in sizes.iter().enumerate() {
+        for _ in 0..size {
+            let pos = spiral.position(n);
+            assert_eq!(k as i64, ring_distance(&pos), "cell {}", n);
+            assert!(seen.insert(pos), "cell {} repeated", n);
+            n += 1;
+        }
+    }
+}
+
+#[test]
+fn hex_spiral_test() {
+    let hex = HexSpiral;
+    let sizes : Vec<u64> = (0..40).map(|k| if k == 0 { 1 } else { 6 * k }).collect();
+    check_shells(&hex, &sizes, |p: &AxialPos| hex.distance(p));
+    assert_sums_keep_every_shell(&hex, 2000);
+    for n in 1..5000 {
+        let (a, b) = (hex.position(n), hex.position(n + 1));
+        assert_eq!(1, hex.distance(&AxialPos{q: b.q - a.q, r: b.r - a.r}), "cell {}", n);
+    }
+    assert_eq!(AxialPos{q: 1, r: -1}, hex.position(2));
+    assert_eq!(AxialPos{q: 1, r: 0}, hex.position(7));
+    assert_eq!(2, spiral_distance(&hex, 8));
+    let sums : Vec<String> = neighbour_sums(&hex).take(9).map(|s| s.2.to_string()).collect();
+    assert_eq!(vec!["1", "1", "2", "3", "4", "5", "7", "8", "9"], sums);
+}
+
+#[test]
+fn cube_spiral_test() {
+    let cube = CubeSpiral;
+    let sizes : Vec<u64> = (0..12u64).map(|k| if k == 0 { 1 } else { (2 * k + 1).pow(3) - (2 * k - 1).pow(3) }).collect();
+    check_shells(&cube, &sizes, |p: &CubePos| cube.shell(p));
+    assert_sums_keep_every_shell(&cube, 2000);
+    assert_eq!(CubePos{x: -1, y: -1, z: -1}, cube.position(2));
+    assert_eq!(3, spiral_distance(&cube, 2));
+    assert_eq!(CubePos{x: 1, y: 1, z: 1}, cube.position(27));
+    let sums : Vec<String> = neighbour_sums(&cube).take(4).map(|s| s.2.to_string()).collect();
+    assert_eq!(vec!["1", "1", "2", "3"], sums);
 }
@@ -1,44 +1,449 @@
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Read};
+use std::fs::File;
+use std::collections::{HashMap,HashSet};
+use std::hash::Hash;
 
 fn valid(p: &Vec<String>) -> bool {
-    let unique : std::collections::HashSet<&String> =
+    let unique : HashSet<&String> =
         p.iter().collect();
     unique.len() == p.len()
 }
 
-fn is_anagram(l: &str, r: &str) -> bool {
+// Anagrams share the same multiset of letters, so sorting them gives a key
+// that is equal exactly for words that are anagrams of each other.
+fn signature(w: &str) -> Vec<char> {
+    let mut sig = w.chars().collect::<Vec<char>>();
+    sig.sort();
+    sig
+}
+
+// Every set of two or more words that are anagrams of each other (repeated
+// words included), in the order their first word appears.
+fn anagram_groups(p: &[String]) -> Vec<Vec<String>> {
+    anagram_groups_by(p, signature)
+}
 
-    let mut lc = l.chars().collect::<Vec<char>>();
-    let mut rc = r.chars().collect::<Vec<char>>();
-    lc.sort();
-    rc.sort();
-    lc == rc
+fn anagram_groups_by<K: Hash + Eq, F: Fn(&str) -> K>(p: &[String], signature: F) -> Vec<Vec<String>> {
+    let mut index : HashMap<K, usize> = HashMap::new();
+    let mut groups : Vec<Vec<String>> = vec![];
+    for w in p {
+        let next = groups.len();
+        let i = *index.entry(signature(w)).or_insert(next);
+        if i == next {
+            groups.push(vec![]);
+        }
+        groups[i].push(w.clone());
+    }
+    groups.into_iter().filter(|g| g.len() > 1).collect()
 }
 
 fn no_anagrams(p: &Vec<String>) -> bool {
-    for (il,l) in p.iter().enumerate() {
-        for (ir,r) in p.iter().enumerate() {
-            if il == ir {
-                continue;
+    anagram_groups(p).is_empty()
+}
+
+fn valid_b(p: &Vec<String>) -> bool {
+    no_anagrams(p) && valid(p)
+}
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+enum Tokenize { SingleSpace, Whitespace }
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+enum Normalization { Unchanged, LatinCompose, LatinDecompose }
+
+// The default splits on single spaces and compares raw chars, which is what
+// the puzzle input expects.
+#[derive(Clone,Copy,PartialEq,Debug)]
+struct TextOptions {
+    tokenize: Tokenize,
+    normalization: Normalization,
+    fold_case: bool,
+    mark_clusters: bool,
+}
+
+impl Default for TextOptions {
+    fn default() -> TextOptions {
+        TextOptions{
+            tokenize: Tokenize::SingleSpace,
+            normalization: Normalization::Unchanged,
+            fold_case: false,
+            mark_clusters: false,
+        }
+    }
+}
+
+impl TextOptions {
+    fn words(&self, line: &str) -> Result<Vec<String>, String> {
+        let split : Vec<&str> = match self.tokenize {
+            Tokenize::SingleSpace   => line.split(' ').collect(),
+            Tokenize::Whitespace    => line.split_whitespace().collect(),
+        };
+        split.into_iter().map(|w| self.word(w)).collect()
+    }
+
+    // Full case folding as far as std gets: uppercasing first takes ß to
+    // ss, and since lowercasing turns a word-final Σ back into ς, that is
+    // folded to σ by hand.
+    fn word(&self, w: &str) -> Result<String, String> {
+        let w = normalize(w, self.normalization)
+            .map_err(|c| format!("'{}' (U+{:04X}) can't be normalized", c, c as u32))?;
+        Ok(if self.fold_case { w.to_uppercase().to_lowercase().replace('\u{3c2}', "\u{3c3}") } else { w })
+    }
+
+    // The pieces a word is compared by: chars, or chars with their marks
+    // so that an accent stays on the letter it was written on.
+    fn units(&self, w: &str) -> Vec<String> {
+        if self.mark_clusters {
+            mark_clusters(w)
+        } else {
+            w.chars().map(|c| c.to_string()).collect()
+        }
+    }
+}
+
+// Canonical decompositions of the precomposed letters in Latin-1 and
+// Latin Extended-A: (composed, base, combining mark).
+const DECOMPOSITIONS: &[(char, char, char)] = &[
+    ('\u{c0}', 'A', '\u{300}'), ('\u{c1}', 'A', '\u{301}'), ('\u{c2}', 'A', '\u{302}'), ('\u{c3}', 'A', '\u{303}'),
+    ('\u{c4}', 'A', '\u{308}'), ('\u{c5}', 'A', '\u{30a}'), ('\u{c7}', 'C', '\u{327}'), ('\u{c8}', 'E', '\u{300}'),
+    ('\u{c9}', 'E', '\u{301}'), ('\u{ca}', 'E', '\u{302}'), ('\u{cb}', 'E', '\u{308}'), ('\u{cc}', 'I', '\u{300}'),
+    ('\u{cd}', 'I', '\u{301}'), ('\u{ce}', 'I', '\u{302}'), ('\u{cf}', 'I', '\u{308}'), ('\u{d1}', 'N', '\u{303}'),
+    ('\u{d2}', 'O', '\u{300}'), ('\u{d3}', 'O', '\u{301}'), ('\u{d4}', 'O', '\u{302}'), ('\u{d5}', 'O', '\u{303}'),
+    ('\u{d6}', 'O', '\u{308}'), ('\u{d9}', 'U', '\u{300}'), ('\u{da}', 'U', '\u{301}'), ('\u{db}', 'U', '\u{302}'),
+    ('\u{dc}', 'U', '\u{308}'), ('\u{dd}', 'Y', '\u{301}'), ('\u{e0}', 'a', '\u{300}'), ('\u{e1}', 'a', '\u{301}'),
+    ('\u{e2}', 'a', '\u{302}'), ('\u{e3}', 'a', '\u{303}'), ('\u{e4}', 'a', '\u{308}'), ('\u{e5}', 'a', '\u{30a}'),
+    ('\u{e7}', 'c', '\u{327}'), ('\u{e8}', 'e', '\u{300}'), ('\u{e9}', 'e', '\u{301}'), ('\u{ea}', 'e', '\u{302}'),
+    ('\u{eb}', 'e', '\u{308}'), ('\u{ec}', 'i', '\u{300}'), ('\u{ed}', 'i', '\u{301}'), ('\u{ee}', 'i', '\u{302}'),
+    ('\u{ef}', 'i', '\u{308}'), ('\u{f1}', 'n', '\u{303}'), ('\u{f2}', 'o', '\u{300}'), ('\u{f3}', 'o', '\u{301}'),
+    ('\u{f4}', 'o', '\u{302}'), ('\u{f5}', 'o', '\u{303}'), ('\u{f6}', 'o', '\u{308}'), ('\u{f9}', 'u', '\u{300}'),
+    ('\u{fa}', 'u', '\u{301}'), ('\u{fb}', 'u', '\u{302}'), ('\u{fc}', 'u', '\u{308}'), ('\u{fd}', 'y', '\u{301}'),
+    ('\u{ff}', 'y', '\u{308}'), ('\u{100}', 'A', '\u{304}'), ('\u{101}', 'a', '\u{304}'), ('\u{102}', 'A', '\u{306}'),
+    ('\u{103}', 'a', '\u{306}'), ('\u{104}', 'A', '\u{328}'), ('\u{105}', 'a', '\u{328}'), ('\u{106}', 'C', '\u{301}'),
+    ('\u{107}', 'c', '\u{301}'), ('\u{108}', 'C', '\u{302}'), ('\u{109}', 'c', '\u{302}'), ('\u{10a}', 'C', '\u{307}'),
+    ('\u{10b}', 'c', '\u{307}'), ('\u{10c}', 'C', '\u{30c}'), ('\u{10d}', 'c', '\u{30c}'), ('\u{10e}', 'D', '\u{30c}'),
+    ('\u{10f}', 'd', '\u{30c}'), ('\u{112}', 'E', '\u{304}'), ('\u{113}', 'e', '\u{304}'), ('\u{114}', 'E', '\u{306}'),
+    ('\u{115}', 'e', '\u{306}'), ('\u{116}', 'E', '\u{307}'), ('\u{117}', 'e', '\u{307}'), ('\u{118}', 'E', '\u{328}'),
+    ('\u{119}', 'e', '\u{328}'), ('\u{11a}', 'E', '\u{30c}'), ('\u{11b}', 'e', '\u{30c}'), ('\u{11c}', 'G', '\u{302}'),
+    ('\u{11d}', 'g', '\u{302}'), ('\u{11e}', 'G', '\u{306}'), ('\u{11f}', 'g', '\u{306}'), ('\u{120}', 'G', '\u{307}'),
+    ('\u{121}', 'g', '\u{307}'), ('\u{122}', 'G', '\u{327}'), ('\u{123}', 'g', '\u{327}'), ('\u{124}', 'H', '\u{302}'),
+    ('\u{125}', 'h', '\u{302}'), ('\u{128}', 'I', '\u{303}'), ('\u{129}', 'i', '\u{303}'), ('\u{12a}', 'I', '\u{304}'),
+    ('\u{12b}', 'i', '\u{304}'), ('\u{12c}', 'I', '\u{306}'), ('\u{12d}', 'i', '\u{306}'), ('\u{12e}', 'I', '\u{328}'),
+    ('\u{12f}', 'i', '\u{328}'), ('\u{130}', 'I', '\u{307}'), ('\u{134}', 'J', '\u{302}'), ('\u{135}', 'j', '\u{302}'),
+    ('\u{136}', 'K', '\u{327}'), ('\u{137}', 'k', '\u{327}'), ('\u{139}', 'L', '\u{301}'), ('\u{13a}', 'l', '\u{301}'),
+    ('\u{13b}', 'L', '\u{327}'), ('\u{13c}', 'l', '\u{327}'), ('\u{13d}', 'L', '\u{30c}'), ('\u{13e}', 'l', '\u{30c}'),
+    ('\u{143}', 'N', '\u{301}'), ('\u{144}', 'n', '\u{301}'), ('\u{145}', 'N', '\u{327}'), ('\u{146}', 'n', '\u{327}'),
+    ('\u{147}', 'N', '\u{30c}'), ('\u{148}', 'n', '\u{30c}'), ('\u{14c}', 'O', '\u{304}'), ('\u{14d}', 'o', '\u{304}'),
+    ('\u{14e}', 'O', '\u{306}'), ('\u{14f}', 'o', '\u{306}'), ('\u{150}', 'O', '\u{30b}'), ('\u{151}', 'o', '\u{30b}'),
+    ('\u{154}', 'R', '\u{301}'), ('\u{155}', 'r', '\u{301}'), ('\u{156}', 'R', '\u{327}'), ('\u{157}', 'r', '\u{327}'),
+    ('\u{158}', 'R', '\u{30c}'), ('\u{159}', 'r', '\u{30c}'), ('\u{15a}', 'S', '\u{301}'), ('\u{15b}', 's', '\u{301}'),
+    ('\u{15c}', 'S', '\u{302}'), ('\u{15d}', 's', '\u{302}'), ('\u{15e}', 'S', '\u{327}'), ('\u{15f}', 's', '\u{327}'),
+    ('\u{160}', 'S', '\u{30c}'), ('\u{161}', 's', '\u{30c}'), ('\u{162}', 'T', '\u{327}'), ('\u{163}', 't', '\u{327}'),
+    ('\u{164}', 'T', '\u{30c}'), ('\u{165}', 't', '\u{30c}'), ('\u{168}', 'U', '\u{303}'), ('\u{169}', 'u', '\u{303}'),
+    ('\u{16a}', 'U', '\u{304}'), ('\u{16b}', 'u', '\u{304}'), ('\u{16c}', 'U', '\u{306}'), ('\u{16d}', 'u', '\u{306}'),
+    ('\u{16e}', 'U', '\u{30a}'), ('\u{16f}', 'u', '\u{30a}'), ('\u{170}', 'U', '\u{30b}'), ('\u{171}', 'u', '\u{30b}'),
+    ('\u{172}', 'U', '\u{328}'), ('\u{173}', 'u', '\u{328}'), ('\u{174}', 'W', '\u{302}'), ('\u{175}', 'w', '\u{302}'),
+    ('\u{176}', 'Y', '\u{302}'), ('\u{177}', 'y', '\u{302}'), ('\u{178}', 'Y', '\u{308}'), ('\u{179}', 'Z', '\u{301}'),
+    ('\u{17a}', 'z', '\u{301}'), ('\u{17b}', 'Z', '\u{307}'), ('\u{17c}', 'z', '\u{307}'), ('\u{17d}', 'Z', '\u{30c}'),
+    ('\u{17e}', 'z', '\u{30c}'),
+];
+
+// Compatibility decompositions applied when decomposing, besides the
+// fullwidth ASCII forms which map arithmetically. Covers every char in
+// Latin-1 and Latin Extended-A that has one, so those ranges can be taken
+// whole.
+const COMPATIBILITY: &[(char, &str)] = &[
+    ('\u{a0}', " "), ('\u{a8}', " \u{308}"), ('\u{aa}', "a"), ('\u{af}', " \u{304}"), ('\u{b2}', "2"),
+    ('\u{b3}', "3"), ('\u{b4}', " \u{301}"), ('\u{b5}', "\u{3bc}"), ('\u{b8}', " \u{327}"), ('\u{b9}', "1"),
+    ('\u{ba}', "o"), ('\u{bc}', "1\u{2044}4"), ('\u{bd}', "1\u{2044}2"), ('\u{be}', "3\u{2044}4"),
+    ('\u{132}', "IJ"), ('\u{133}', "ij"), ('\u{13f}', "L\u{b7}"), ('\u{140}', "l\u{b7}"), ('\u{149}', "\u{2bc}n"),
+    ('\u{17f}', "s"), ('\u{fb00}', "ff"), ('\u{fb01}', "fi"), ('\u{fb02}', "fl"), ('\u{fb03}', "ffi"),
+    ('\u{fb04}', "ffl"), ('\u{fb05}', "st"), ('\u{fb06}', "st"),
+];
+
+fn combining_class(c: char) -> u8 {
+    match c as u32 {
+        0x0315 | 0x031a | 0x0358    => 232,
+        0x031b                      => 216,
+        0x0321 | 0x0322 | 0x0327 | 0x0328 => 202,
+        0x0334..=0x0338             => 1,
+        0x0345                      => 240,
+        0x034f                      => 0,
+        0x035c | 0x035f | 0x0362    => 233,
+        0x035d | 0x035e | 0x0360 | 0x0361 => 234,
+        0x0316..=0x0319 | 0x031c..=0x0320 | 0x0323..=0x0326 | 0x0329..=0x0333 |
+        0x0339..=0x033c | 0x0347..=0x0349 | 0x034d | 0x034e | 0x0353..=0x0356 |
+        0x0359 | 0x035a             => 220,
+        0x0300..=0x036f             => 230,
+        0x1ab0..=0x1aff | 0x1dc0..=0x1dff | 0x20d0..=0x20ff | 0xfe20..=0xfe2f => 230,
+        _                           => 0,
+    }
+}
+
+fn decompose(c: char, compatibility: bool, out: &mut Vec<char>) {
+    if compatibility {
+        if let 0xff01..=0xff5e = c as u32 {
+            out.push(std::char::from_u32(c as u32 - 0xfee0).unwrap());
+            return
+        }
+        if let Some(&(_, s)) = COMPATIBILITY.iter().find(|&&(k, _)| k == c) {
+            out.extend(s.chars());
+            return
+        }
+    }
+    match DECOMPOSITIONS.iter().find(|&&(k, _, _)| k == c) {
+        Some(&(_, base, mark))  => { out.push(base); out.push(mark); },
+        None                    => out.push(c),
+    }
+}
+
+// Canonical composition: a mark joins the last starter unless another mark
+// of the same or a higher class sits between them.
+fn compose(chars: &[char]) -> Vec<char> {
+    let mut out : Vec<char> = vec![];
+    let mut starter = None;
+    let mut last_class = 0;
+    for &c in chars {
+        let class = combining_class(c);
+        if let Some(i) = starter {
+            let blocked = last_class != 0 && last_class >= class;
+            if class != 0 && !blocked {
+                if let Some(&(composed, _, _)) = DECOMPOSITIONS.iter().find(|&&(_, b, m)| b == out[i] && m == c) {
+                    out[i] = composed;
+                    continue
+                }
             }
-            if is_anagram(l, r) {
-                return false;
+        }
+        if class == 0 {
+            starter = Some(out.len());
+        }
+        last_class = class;
+        out.push(c);
+    }
+    out
+}
+
+// Whether the tables above know everything about a char: ASCII, Latin-1,
+// Latin Extended-A, combining diacritics and the compatibility forms.
+fn normalizable(c: char) -> bool {
+    match c as u32 {
+        0..=0x17f | 0x300..=0x36f | 0xff01..=0xff5e => true,
+        _                                           => COMPATIBILITY.iter().any(|&(k, _)| k == c),
+    }
+}
+
+// Not Unicode normalization: composes or decomposes only the letters in
+// the tables, and gives back the first char they don't cover instead of
+// passing it through half-normalized.
+fn normalize(w: &str, n: Normalization) -> Result<String, char> {
+    if n == Normalization::Unchanged {
+        return Ok(w.to_owned())
+    }
+    let mut chars = vec![];
+    for c in w.chars() {
+        if !normalizable(c) {
+            return Err(c)
+        }
+        decompose(c, n == Normalization::LatinDecompose, &mut chars);
+    }
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        while i < chars.len() && combining_class(chars[i]) != 0 {
+            i += 1;
+        }
+        chars[start..i].sort_by_key(|&c| combining_class(c));
+        i += 1;
+    }
+    if n == Normalization::LatinCompose {
+        chars = compose(&chars);
+    }
+    Ok(chars.into_iter().collect())
+}
+
+fn extends_cluster(c: char) -> bool {
+    combining_class(c) != 0 || matches!(c as u32, 0x200d | 0xfe00..=0xfe0f | 0x1f3fb..=0x1f3ff)
+}
+
+// A char followed by its combining marks, variation selectors and emoji
+// modifiers, with zero width joiners gluing the next char on as well. Not
+// Unicode grapheme segmentation: Hangul syllables, regional indicator
+// pairs, prepended marks and the like are split char by char.
+fn mark_clusters(w: &str) -> Vec<String> {
+    let mut clusters : Vec<String> = vec![];
+    let mut joined = false;
+    for c in w.chars() {
+        match clusters.last_mut() {
+            Some(last) if joined || extends_cluster(c) => last.push(c),
+            _                                         => clusters.push(c.to_string()),
+        }
+        joined = c == '\u{200d}';
+    }
+    clusters
+}
+
+#[derive(Debug,Clone,PartialEq)]
+enum Rule {
+    UniqueWords,
+    NoAnagrams,
+    MinWords(usize),
+    MaxWords(usize),
+    NoPalindromes,
+    NoRotations,
+    Blacklist(HashSet<String>),
+}
+
+fn is_rotation(l: &[String], r: &[String]) -> bool {
+    l.len() == r.len() && (0..l.len().max(1)).any(|i| l[i..].iter().chain(&l[..i]).eq(r.iter()))
+}
+
+fn offending_pairs<F: Fn(&str, &str) -> bool>(p: &[String], f: F) -> Vec<String> {
+    let mut words = vec![];
+    for (il, l) in p.iter().enumerate() {
+        for r in &p[il+1..] {
+            if f(l, r) {
+                words.push(l.clone());
+                words.push(r.clone());
             }
         }
     }
-    true
+    words
 }
 
-fn valid_b(p: &Vec<String>) -> bool {
-    no_anagrams(p) && valid(p)
+impl Rule {
+    fn name(&self) -> &'static str {
+        match *self {
+            Rule::UniqueWords       => "unique-words",
+            Rule::NoAnagrams        => "no-anagrams",
+            Rule::MinWords(_)       => "min-words",
+            Rule::MaxWords(_)       => "max-words",
+            Rule::NoPalindromes     => "no-palindromes",
+            Rule::NoRotations       => "no-rotations",
+            Rule::Blacklist(_)      => "blacklist",
+        }
+    }
+
+    // The words that break the rule, or None when the passphrase passes.
+    fn check(&self, p: &[String], text: &TextOptions) -> Option<Vec<String>> {
+        let words = match *self {
+            Rule::UniqueWords       => anagram_groups_by(p, |w| w.to_owned()).concat(),
+            Rule::NoAnagrams        => if text.mark_clusters {
+                anagram_groups_by(p, |w| { let mut g = mark_clusters(w); g.sort(); g }).concat()
+            } else {
+                anagram_groups(p).concat()
+            },
+            Rule::MinWords(n)       => return if p.len() < n { Some(p.to_vec()) } else { None },
+            Rule::MaxWords(n)       => return if p.len() > n { Some(p.to_vec()) } else { None },
+            Rule::NoPalindromes     => p.iter()
+                .filter(|w| { let u = text.units(w); u.iter().eq(u.iter().rev()) })
+                .cloned()
+                .collect(),
+            Rule::NoRotations       => offending_pairs(p, |l, r| is_rotation(&text.units(l), &text.units(r))),
+            Rule::Blacklist(ref b)  => p.iter().filter(|w| b.contains(*w)).cloned().collect(),
+        };
+        if words.is_empty() { None } else { Some(words) }
+    }
+}
+
+#[derive(Debug,PartialEq)]
+struct Rejection {
+    rule: &'static str,
+    words: Vec<String>,
+}
+
+struct Policy {
+    rules: Vec<Rule>,
+    text: TextOptions,
+}
+
+impl Policy {
+    // One rule per line, e.g. `min-words 3` or `blacklist foo bar`; blank
+    // lines and lines starting with `#` are ignored. `tokenize`,
+    // `normalize`, `fold-case` and `mark-clusters` lines set the text options.
+    fn parse(config: &str) -> Result<Policy, String> {
+        let mut rules = vec![];
+        let mut text = TextOptions::default();
+        let mut blacklists = vec![];
+        for (i, line) in config.lines().enumerate() {
+            let mut split = line.split_whitespace();
+            let name = match split.next() {
+                Some(n) if !n.starts_with('#')  => n,
+                _                               => continue,
+            };
+            let args : Vec<&str> = split.collect();
+            let count = || match args.first().map(|a| a.parse::<usize>()) {
+                Some(Ok(n)) => Ok(n),
+                _           => Err(format!("line {}: '{}' needs a word count", i + 1, name)),
+            };
+            let option = || Err(format!("line {}: bad value for '{}'", i + 1, name));
+            match (name, args.first().cloned()) {
+                ("tokenize", Some("single-space"))      => { text.tokenize = Tokenize::SingleSpace; continue },
+                ("tokenize", Some("whitespace"))        => { text.tokenize = Tokenize::Whitespace; continue },
+                ("normalize", Some("none"))             => { text.normalization = Normalization::Unchanged; continue },
+                ("normalize", Some("latin-compose"))    => { text.normalization = Normalization::LatinCompose; continue },
+                ("normalize", Some("latin-decompose"))  => { text.normalization = Normalization::LatinDecompose; continue },
+                ("fold-case", None)                     => { text.fold_case = true; continue },
+                ("mark-clusters", None)                 => { text.mark_clusters = true; continue },
+                ("tokenize", _) | ("normalize", _) | ("fold-case", _) | ("mark-clusters", _) => return option(),
+                _                                       => {},
+            }
+            let rule = match name {
+                "unique-words"      => Rule::UniqueWords,
+                "no-anagrams"       => Rule::NoAnagrams,
+                "min-words"         => Rule::MinWords(count()?),
+                "max-words"         => Rule::MaxWords(count()?),
+                "no-palindromes"    => Rule::NoPalindromes,
+                "no-rotations"      => Rule::NoRotations,
+                "blacklist"         => {
+                    blacklists.push((rules.len(), i + 1, args));
+                    Rule::Blacklist(HashSet::new())
+                },
+                _                   => return Err(format!("line {}: unknown rule '{}'", i + 1, name)),
+            };
+            rules.push(rule);
+        }
+        // The text options may come after a blacklist, so its words are only
+        // put through them once the whole config is read.
+        for (at, line, args) in blacklists {
+            let words = args.iter().map(|w| text.word(w)).collect::<Result<_, _>>();
+            rules[at] = Rule::Blacklist(words.map_err(|e| format!("line {}: {}", line, e))?);
+        }
+        Ok(Policy{rules, text})
+    }
+
+    fn check(&self, p: &[String]) -> Result<(), Rejection> {
+        for rule in &self.rules {
+            if let Some(words) = rule.check(p, &self.text) {
+                return Err(Rejection{rule: rule.name(), words})
+            }
+        }
+        Ok(())
+    }
 }
 
 fn main() {
-    let passphrases : Vec<String> = BufReader::new(std::io::stdin()).lines().map(|l| l.unwrap()).collect();
-    let passphrases : Vec<Vec<String>> = passphrases.iter().map(|l| l.split(" ").map(|x| x.to_owned()).collect()).collect();
+    let lines : Vec<String> = BufReader::new(std::io::stdin()).lines().map(|l| l.unwrap()).collect();
+    if let Some(path) = std::env::args().nth(1) {
+        let mut config = String::new();
+        File::open(path).unwrap().read_to_string(&mut config).unwrap();
+        let policy = Policy::parse(&config).unwrap_or_else(|e| panic!("{}", e));
+        let mut count = 0;
+        for (i, l) in lines.iter().enumerate() {
+            let p = match policy.text.words(l) {
+                Ok(p)   => p,
+                Err(e)  => { println!("line {}: {}", i + 1, e); continue },
+            };
+            match policy.check(&p) {
+                Ok(())  => count += 1,
+                Err(r)  => println!("line {}: {}: {}", i + 1, r.rule, r.words.join(" ")),
+            }
+        }
+        println!("{}", count);
+        return
+    }
+    let passphrases : Vec<Vec<String>> = lines.iter().map(|l| TextOptions::default().words(l).unwrap()).collect();
     let a = passphrases.iter().filter(|r| valid(r)).count();
     println!("{}", a);
-    let b = passphrases.iter().filter(|r| valid(r) && no_anagrams(r)).count();
+    let b = passphrases.iter().filter(|r| valid_b(r)).count();
     println!("{}", b);
 }
 
@@ -52,3 +457,141 @@ fn valid_test() {
 fn valid_b_test() {
     assert!(valid_b(&vec!["abcde".to_owned(), "fghij".to_owned()]));
 }
+
+#[cfg(test)]
+fn words(s: &str) -> Vec<String> {
+    s.split_whitespace().map(|w| w.to_owned()).collect()
+}
+
+#[test]
+fn policy_test() {
+    let policy = Policy::parse("# part b\nunique-words\n\nno-anagrams\n").unwrap();
+    assert_eq!(Ok(()), policy.check(&words("abcde fghij")));
+    assert_eq!(Err(Rejection{rule: "no-anagrams", words: words("abcde ecdab")}),
+               policy.check(&words("abcde xyz ecdab")));
+    assert_eq!(Err(Rejection{rule: "unique-words", words: words("aa aa")}),
+               policy.check(&words("aa bb aa")));
+    assert_eq!(Err(Rejection{rule: "unique-words", words: words("aa aa aa bb bb")}),
+               policy.check(&words("aa bb aa cc bb aa")));
+    // Each occurrence is reported once, not once per pair it is part of.
+    let p = vec!["same".to_owned(); 3000];
+    assert_eq!(3000, policy.check(&p).unwrap_err().words.len());
+}
+
+#[test]
+fn policy_rules_test() {
+    let policy = Policy::parse("min-words 2\nmax-words 3\nno-palindromes\nno-rotations\nblacklist foo bar").unwrap();
+    assert_eq!(Ok(()), policy.check(&words("abc def")));
+    assert_eq!("min-words", policy.check(&words("abc")).unwrap_err().rule);
+    assert_eq!("max-words", policy.check(&words("a b c d")).unwrap_err().rule);
+    assert_eq!(Err(Rejection{rule: "no-palindromes", words: words("level")}),
+               policy.check(&words("abc level")));
+    assert_eq!(Err(Rejection{rule: "no-rotations", words: words("abcd cdab")}),
+               policy.check(&words("abcd cdab")));
+    assert_eq!(Err(Rejection{rule: "blacklist", words: words("bar")}),
+               policy.check(&words("abc bar")));
+}
+
+#[test]
+fn policy_parse_error_test() {
+    assert_eq!(Some("line 2: unknown rule 'no-vowels'".to_owned()),
+               Policy::parse("unique-words\nno-vowels").err());
+    assert_eq!(Some("line 1: 'min-words' needs a word count".to_owned()),
+               Policy::parse("min-words many").err());
+}
+
+#[test]
+fn anagram_groups_test() {
+    assert_eq!(Vec::<Vec<String>>::new(), anagram_groups(&words("abcde fghij")));
+    assert_eq!(vec![words("abcde ecdab"), words("oo oo")],
+               anagram_groups(&words("abcde xyz oo ecdab oo")));
+    assert_eq!(vec![words("iiii iiii")], anagram_groups(&words("iiii oiii ooii oooi iiii")));
+    assert!(no_anagrams(&words("a ab abc abd abf abj")));
+    assert!(!no_anagrams(&words("oiii ioii iioi iiio")));
+}
+
+#[test]
+fn anagram_groups_many_words_test() {
+    let mut p : Vec<String> = (0..20_000u32)
+        .map(|i| format!("{:05}", i).chars().map(|c| (b'a' + c as u8 - b'0') as char).collect())
+        .collect();
+    assert!(!no_anagrams(&p));
+    p.retain(|w| signature(w) == w.chars().collect::<Vec<char>>());
+    assert!(no_anagrams(&p));
+    p.push("edcba".to_owned());
+    assert_eq!(vec![words("abcde edcba")], anagram_groups(&p));
+}
+
+#[test]
+fn text_options_words_test() {
+    let mut text = TextOptions::default();
+    assert_eq!(Ok(vec!["a".to_owned(), "".to_owned(), "b\tc".to_owned()]), text.words("a  b\tc"));
+    text.tokenize = Tokenize::Whitespace;
+    assert_eq!(Ok(words("a b c")), text.words("a  b\tc"));
+    text.fold_case = true;
+    assert_eq!(Ok(words("strasse éa strasse")), text.words("STRAßE \u{c9}A STRASSE"));
+    assert_eq!(Ok(words("\u{3c3}\u{3bf}\u{3c6}\u{3bf}\u{3c3} \u{3c3}\u{3bf}\u{3c6}\u{3bf}\u{3c3}")),
+               text.words("\u{3c3}\u{3bf}\u{3c6}\u{3bf}\u{3c2} \u{3a3}\u{39f}\u{3a6}\u{39f}\u{3a3}"));
+    text.normalization = Normalization::LatinCompose;
+    assert_eq!(Err("'\u{3b1}' (U+03B1) can't be normalized".to_owned()), text.words("b \u{3b1}\u{301}"));
+}
+
+#[test]
+fn normalize_test() {
+    let decomposed = "e\u{301}te\u{301}";
+    assert_eq!(Ok("\u{e9}t\u{e9}".to_owned()), normalize(decomposed, Normalization::LatinCompose));
+    assert_eq!(Ok(decomposed.to_owned()), normalize("\u{e9}t\u{e9}", Normalization::LatinDecompose));
+    assert_eq!(Ok("fi1".to_owned()), normalize("\u{fb01}\u{b9}", Normalization::LatinDecompose));
+    assert_eq!(Ok("AB".to_owned()), normalize("\u{ff21}\u{ff22}", Normalization::LatinDecompose));
+    assert_eq!(Ok("\u{fb01}".to_owned()), normalize("\u{fb01}", Normalization::LatinCompose));
+    assert_eq!(Ok("1\u{2044}4u\u{302}sij".to_owned()), normalize("\u{bc}\u{fb}\u{17f}\u{133}", Normalization::LatinDecompose));
+    assert_eq!(Ok(" \u{301} \u{308} \u{304} \u{327}L\u{b7}\u{2bc}n".to_owned()),
+               normalize("\u{b4}\u{a8}\u{af}\u{b8}\u{13f}\u{149}", Normalization::LatinDecompose));
+    assert_eq!(Ok("\u{bc}\u{fb}\u{17f}\u{133}".to_owned()), normalize("\u{bc}u\u{302}\u{17f}\u{133}", Normalization::LatinCompose));
+    // The cedilla (class 202) sorts before the acute (230), and both compose
+    // only as far as the table allows.
+    assert_eq!(Ok("c\u{327}\u{301}".to_owned()), normalize("c\u{301}\u{327}", Normalization::LatinDecompose));
+    assert_eq!(Ok("\u{e7}\u{301}".to_owned()), normalize("c\u{301}\u{327}", Normalization::LatinCompose));
+    // Anything the tables don't cover is refused rather than left as is.
+    assert_eq!(Err('\u{3b1}'), normalize("\u{3b1}\u{301}", Normalization::LatinCompose));
+    assert_eq!(Err('\u{1ec7}'), normalize("\u{1ec7}", Normalization::LatinDecompose));
+    assert_eq!(Ok("\u{3b1}\u{301}".to_owned()), normalize("\u{3b1}\u{301}", Normalization::Unchanged));
+}
+
+#[test]
+fn mark_clusters_test() {
+    assert_eq!(vec!["e\u{301}", "a"], mark_clusters("e\u{301}a"));
+    assert_eq!(vec!["\u{1f469}\u{200d}\u{1f52c}", "x"], mark_clusters("\u{1f469}\u{200d}\u{1f52c}x"));
+    assert_eq!(vec!["\u{1f44d}\u{1f3fd}"], mark_clusters("\u{1f44d}\u{1f3fd}"));
+    assert_eq!(vec!["\u{1f1eb}", "\u{1f1f7}"], mark_clusters("\u{1f1eb}\u{1f1f7}"));
+}
+
+#[test]
+fn policy_unicode_test() {
+    let plain = Policy::parse("no-anagrams\nunique-words").unwrap();
+    let local = Policy::parse("tokenize whitespace\nnormalize latin-compose\nfold-case\nmark-clusters\nno-anagrams\nunique-words").unwrap();
+    // Same word, once precomposed and once with a combining accent.
+    let line = "caf\u{e9}\tCafe\u{301}";
+    assert_eq!(Ok(()), plain.check(&plain.text.words(line).unwrap()));
+    assert_eq!(Err(Rejection{rule: "no-anagrams", words: words("caf\u{e9} caf\u{e9}")}),
+               local.check(&local.text.words(line).unwrap()));
+    // Moving the accent to another letter is not an anagram.
+    let line = "e\u{301}a a\u{301}e";
+    assert!(plain.check(&plain.text.words(line).unwrap()).is_err());
+    let clusters_only = Policy::parse("mark-clusters\nno-anagrams").unwrap();
+    assert_eq!(Ok(()), clusters_only.check(&clusters_only.text.words(line).unwrap()));
+    // Greek is beyond the tables, so the line is refused instead of passing.
+    assert!(local.text.words("\u{3ac} \u{3b2} / \u{3b2} \u{3b1}\u{301}").is_err());
+    assert_eq!(Some("line 1: bad value for 'normalize'".to_owned()), Policy::parse("normalize nfc").err());
+}
+
+#[test]
+fn policy_blacklist_text_options_test() {
+    let policy = Policy::parse("blacklist Foo caf\u{e9}\nfold-case\nnormalize latin-decompose").unwrap();
+    assert_eq!(Err(Rejection{rule: "blacklist", words: words("foo")}),
+               policy.check(&policy.text.words("FOO bar").unwrap()));
+    assert_eq!(Err(Rejection{rule: "blacklist", words: words("cafe\u{301}")}),
+               policy.check(&policy.text.words("caf\u{e9}").unwrap()));
+    assert_eq!(Some("line 2: '\u{3b1}' (U+03B1) can't be normalized".to_owned()),
+               Policy::parse("normalize latin-compose\nblacklist \u{3b1}").err());
+}
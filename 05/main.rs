@@ -1,38 +1,396 @@
 use std::io::{BufRead,BufReader};
+use std::collections::HashMap;
 
-fn execute(instr: &[i32]) -> i32 {
-    let mut c = 0;
+fn increment(offset: i32) -> Option<i32> {
+    offset.checked_add(1)
+}
+
+fn stranger(offset: i32) -> Option<i32> {
+    if offset >= 3 { offset.checked_sub(1) } else { offset.checked_add(1) }
+}
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+enum Guard {
+    Unbounded,
+    Steps(u64),
+    // Remembers every (position, offsets) pair, so only for small programs.
+    States,
+}
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+enum Outcome {
+    Exited{steps: u64},
+    Looped{steps: u64, period: u64},
+    GaveUp{steps: u64},
+    // The update rule gave no offset for the instruction at `at`.
+    Overflowed{steps: u64, at: usize},
+}
+
+fn run<F: Fn(i32) -> Option<i32>>(instr: &[i32], update: F, guard: Guard) -> Outcome {
+    simulate(instr, update, guard, |_| {}).0
+}
+
+// Calls `visit` with every executed position and returns the outcome along
+// with the final offsets and the position the program stopped at.
+fn simulate<F, V>(instr: &[i32], update: F, guard: Guard, mut visit: V) -> (Outcome, Vec<i32>, i64)
+    where F: Fn(i32) -> Option<i32>, V: FnMut(usize)
+{
+    let mut steps = 0;
     let mut buf : Vec<i32> = instr.to_vec();
-    let mut pos : i32 = 0;
-    while pos < buf.len() as i32 && pos >= 0 {
-        c += 1;
+    let mut pos : i64 = 0;
+    let mut seen = HashMap::new();
+    while pos < buf.len() as i64 && pos >= 0 {
+        match guard {
+            Guard::Unbounded    => {},
+            Guard::Steps(max)   => if steps == max {
+                return (Outcome::GaveUp{steps}, buf, pos)
+            },
+            Guard::States       => if let Some(first) = seen.insert((pos, buf.clone()), steps) {
+                return (Outcome::Looped{steps, period: steps - first}, buf, pos)
+            },
+        }
+        steps += 1;
         let old = pos as usize;
-        pos = pos + buf[pos as usize];
-        buf[old] += 1;
+        visit(old);
+        pos += buf[old] as i64;
+        buf[old] = match update(buf[old]) {
+            Some(o) => o,
+            None    => return (Outcome::Overflowed{steps, at: old}, buf, pos),
+        };
     }
-    c
+    (Outcome::Exited{steps}, buf, pos)
 }
 
-fn execute_b(instr: &[i32]) -> i32 {
-    let mut c = 0;
-    let mut buf : Vec<i32> = instr.to_vec();
-    let mut pos : i32 = 0;
-    while pos < buf.len() as i32 && pos >= 0 {
-        c += 1;
-        let old = pos as usize;
-        pos = pos + buf[pos as usize];
-        if buf[old] >= 3 {
-            buf[old] -= 1;
-        } else {
-            buf[old] += 1;
+// Overflowed means the offset at `last` couldn't be updated, so the
+// program stopped there.
+#[derive(Clone,Copy,PartialEq,Debug)]
+enum Exit { Left, Right, Overflowed }
+
+#[derive(PartialEq,Debug)]
+struct Profile {
+    steps: u64,
+    visits: Vec<u64>,
+    offsets: Vec<i32>,
+    exit: Exit,
+    // The last instruction executed before jumping out.
+    last: usize,
+}
+
+impl Profile {
+    fn to_csv(&self) -> String {
+        let mut csv = "index,visits,final_offset,last\n".to_owned();
+        for (i, (v, o)) in self.visits.iter().zip(&self.offsets).enumerate() {
+            csv += &format!("{},{},{},{}\n", i, v, o, (i == self.last) as u8);
+        }
+        csv
+    }
+}
+
+fn profile<F: Fn(i32) -> Option<i32>>(instr: &[i32], update: F) -> Profile {
+    let mut visits = vec![0; instr.len()];
+    let mut last = 0;
+    let (outcome, offsets, pos) = simulate(instr, update, Guard::Unbounded, |p| { visits[p] += 1; last = p; });
+    let (steps, exit) = match outcome {
+        Outcome::Exited{steps} if pos < 0   => (steps, Exit::Left),
+        Outcome::Exited{steps}              => (steps, Exit::Right),
+        Outcome::Overflowed{steps, ..}      => (steps, Exit::Overflowed),
+        // Without a guard the run can only end one of those two ways.
+        _                                   => unreachable!(),
+    };
+    Profile{steps, visits, offsets, exit, last}
+}
+
+fn execute_profile(instr: &[i32]) -> Profile {
+    profile(instr, increment)
+}
+
+fn execute_b_profile(instr: &[i32]) -> Profile {
+    profile(instr, stranger)
+}
+
+// A position left of the table wraps around to a huge usize, so a single
+// `get_mut` both bounds checks and detects either exit.
+fn execute(instr: &[i32]) -> u64 {
+    let mut steps = 0;
+    let mut buf : Vec<i64> = instr.iter().map(|&o| o as i64).collect();
+    let mut pos = 0usize;
+    while let Some(o) = buf.get_mut(pos) {
+        steps += 1;
+        pos = pos.wrapping_add(*o as usize);
+        *o += 1;
+    }
+    steps
+}
+
+const BLOCK: usize = 12;
+
+// Under the part B rule a 2 or a 3 only ever turns into the other one, so
+// once a prefix of the table holds nothing else it is kept as bitmasks of
+// BLOCK cells (bit set for 3). Passing forward through a block then only
+// depends on its mask and the entry offset, which is memoized.
+struct StableBlocks {
+    masks: Vec<u16>,
+    // mask * BLOCK + entry -> new mask << 16 | steps << 8 | exit past the block
+    memo: Vec<u32>,
+}
+
+impl StableBlocks {
+    fn new() -> StableBlocks {
+        StableBlocks{masks: vec![], memo: vec![]}
+    }
+
+    fn len(&self) -> usize {
+        self.masks.len() * BLOCK
+    }
+
+    fn push(&mut self, cells: &[i64]) {
+        let mask = cells.iter().enumerate().fold(0, |m, (i, &o)| if o == 3 { m | 1 << i } else { m });
+        self.masks.push(mask);
+    }
+
+    // Runs forward through the blocks from `pos` until leaving the prefix.
+    fn pass(&mut self, mut pos: usize) -> (usize, u64) {
+        if self.memo.is_empty() {
+            self.memo = vec![u32::MAX; BLOCK << BLOCK];
+        }
+        let mut steps = 0;
+        while pos < self.len() {
+            let block = pos / BLOCK;
+            let key = self.masks[block] as usize * BLOCK + pos % BLOCK;
+            if self.memo[key] == u32::MAX {
+                self.memo[key] = walk_block(self.masks[block], pos % BLOCK);
+            }
+            let m = self.memo[key];
+            self.masks[block] = (m >> 16) as u16;
+            pos = (block + 1) * BLOCK + (m & 0xff) as usize;
+            steps += (m >> 8 & 0xff) as u64;
+        }
+        (pos, steps)
+    }
+}
+
+fn walk_block(mut mask: u16, mut p: usize) -> u32 {
+    let mut steps = 0;
+    while p < BLOCK {
+        let three = mask & 1 << p != 0;
+        mask ^= 1 << p;
+        p += if three { 3 } else { 2 };
+        steps += 1;
+    }
+    (mask as u32) << 16 | steps << 8 | (p - BLOCK) as u32
+}
+
+fn execute_b(instr: &[i32]) -> u64 {
+    let mut steps = 0;
+    let mut buf : Vec<i64> = instr.iter().map(|&o| o as i64).collect();
+    let mut pos = 0usize;
+    let mut stable = 0;
+    let mut blocks = StableBlocks::new();
+    loop {
+        if pos < blocks.len() {
+            let (next, taken) = blocks.pass(pos);
+            pos = next;
+            steps += taken;
+        }
+        let o = match buf.get_mut(pos) {
+            Some(o) => o,
+            None    => break,
+        };
+        steps += 1;
+        let old = pos;
+        pos = pos.wrapping_add(*o as usize);
+        *o += if *o >= 3 { -1 } else { 1 };
+        if old == stable {
+            while stable < buf.len() && (buf[stable] == 2 || buf[stable] == 3) {
+                stable += 1;
+            }
+            while blocks.len() + BLOCK <= stable {
+                let start = blocks.len();
+                blocks.push(&buf[start..start + BLOCK]);
+            }
+        }
+    }
+    steps
+}
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+enum BinOp { Add, Sub, Mul, Lt, Le, Gt, Ge, Eq, Ne }
+
+// Offset update rules written as expressions over the current offset `o`,
+// e.g. `o >= 3 ? o - 1 : o + 1`. Comparisons yield 1 or 0.
+#[derive(Clone,PartialEq,Debug)]
+enum Expr {
+    Offset,
+    Num(i32),
+    Neg(Box<Expr>),
+    Bin(Box<Expr>, BinOp, Box<Expr>),
+    Cond(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    // None when the arithmetic overflows i32.
+    fn eval(&self, o: i32) -> Option<i32> {
+        match *self {
+            Expr::Offset            => Some(o),
+            Expr::Num(n)            => Some(n),
+            Expr::Neg(ref e)        => e.eval(o)?.checked_neg(),
+            Expr::Bin(ref l, op, ref r) => {
+                let (l, r) = (l.eval(o)?, r.eval(o)?);
+                match op {
+                    BinOp::Add  => l.checked_add(r),
+                    BinOp::Sub  => l.checked_sub(r),
+                    BinOp::Mul  => l.checked_mul(r),
+                    BinOp::Lt   => Some((l < r) as i32),
+                    BinOp::Le   => Some((l <= r) as i32),
+                    BinOp::Gt   => Some((l > r) as i32),
+                    BinOp::Ge   => Some((l >= r) as i32),
+                    BinOp::Eq   => Some((l == r) as i32),
+                    BinOp::Ne   => Some((l != r) as i32),
+                }
+            },
+            Expr::Cond(ref c, ref t, ref f) => if c.eval(o)? != 0 { t.eval(o) } else { f.eval(o) },
+        }
+    }
+
+    fn parse(input: &str) -> Result<Expr, String> {
+        let mut tokens = tokenize(input)?;
+        tokens.reverse();
+        let e = parse_cond(&mut tokens)?;
+        match tokens.pop() {
+            None    => Ok(e),
+            Some(t) => Err(format!("unexpected '{}'", t)),
         }
     }
-    c
+}
+
+fn tokenize(input: &str) -> Result<Vec<String>, String> {
+    let mut tokens = vec![];
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t'             => {},
+            'o' | '+' | '-' | '*' | '?' | ':' | '(' | ')' => tokens.push(c.to_string()),
+            '<' | '>' | '=' | '!'   => {
+                let mut t = c.to_string();
+                if chars.peek() == Some(&'=') {
+                    t.push(chars.next().unwrap());
+                }
+                tokens.push(t);
+            },
+            '0'..='9'               => {
+                let mut t = c.to_string();
+                while chars.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    t.push(chars.next().unwrap());
+                }
+                tokens.push(t);
+            },
+            _                       => return Err(format!("unexpected '{}'", c)),
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_cond(tokens: &mut Vec<String>) -> Result<Expr, String> {
+    let c = parse_cmp(tokens)?;
+    if tokens.last().map(|t| t.as_str()) != Some("?") {
+        return Ok(c)
+    }
+    tokens.pop();
+    let t = parse_cond(tokens)?;
+    if tokens.pop().as_deref() != Some(":") {
+        return Err("expected ':'".to_owned())
+    }
+    let f = parse_cond(tokens)?;
+    Ok(Expr::Cond(Box::new(c), Box::new(t), Box::new(f)))
+}
+
+fn parse_cmp(tokens: &mut Vec<String>) -> Result<Expr, String> {
+    let l = parse_sum(tokens)?;
+    let op = match tokens.last().map(|t| t.as_str()) {
+        Some("<")   => BinOp::Lt,
+        Some("<=")  => BinOp::Le,
+        Some(">")   => BinOp::Gt,
+        Some(">=")  => BinOp::Ge,
+        Some("==")  => BinOp::Eq,
+        Some("!=")  => BinOp::Ne,
+        _           => return Ok(l),
+    };
+    tokens.pop();
+    let r = parse_sum(tokens)?;
+    Ok(Expr::Bin(Box::new(l), op, Box::new(r)))
+}
+
+fn parse_sum(tokens: &mut Vec<String>) -> Result<Expr, String> {
+    let mut l = parse_product(tokens)?;
+    loop {
+        let op = match tokens.last().map(|t| t.as_str()) {
+            Some("+")   => BinOp::Add,
+            Some("-")   => BinOp::Sub,
+            _           => return Ok(l),
+        };
+        tokens.pop();
+        let r = parse_product(tokens)?;
+        l = Expr::Bin(Box::new(l), op, Box::new(r));
+    }
+}
+
+fn parse_product(tokens: &mut Vec<String>) -> Result<Expr, String> {
+    let mut l = parse_atom(tokens)?;
+    while tokens.last().map(|t| t.as_str()) == Some("*") {
+        tokens.pop();
+        let r = parse_atom(tokens)?;
+        l = Expr::Bin(Box::new(l), BinOp::Mul, Box::new(r));
+    }
+    Ok(l)
+}
+
+fn parse_atom(tokens: &mut Vec<String>) -> Result<Expr, String> {
+    let t = tokens.pop().ok_or("unexpected end of rule".to_owned())?;
+    match t.as_str() {
+        "o" => Ok(Expr::Offset),
+        "-" => Ok(Expr::Neg(Box::new(parse_atom(tokens)?))),
+        "(" => {
+            let e = parse_cond(tokens)?;
+            if tokens.pop().as_deref() != Some(")") {
+                return Err("expected ')'".to_owned())
+            }
+            Ok(e)
+        },
+        _   => t.parse().map(Expr::Num).map_err(|_| format!("unexpected '{}'", t)),
+    }
 }
 
 fn main() {
     let lines : Result<Vec<String>, _> = BufReader::new(std::io::stdin()).lines().collect();
     let instructions : Vec<i32> = lines.unwrap().into_iter().map(|l| l.parse::<i32>().unwrap()).collect();
+    let args : Vec<String> = std::env::args().collect();
+    if args.len() > 1 && args[1] == "profile" {
+        let p = if args.get(2).map(|s| s.as_str()) == Some("b") {
+            execute_b_profile(&instructions)
+        } else {
+            execute_profile(&instructions)
+        };
+        match p.exit {
+            Exit::Overflowed    => eprintln!("{} steps, offset {} overflowed", p.steps, p.last),
+            _                   => eprintln!("{} steps, exited {:?} from {}", p.steps, p.exit, p.last),
+        }
+        print!("{}", p.to_csv());
+        return
+    }
+    if let Some(rule) = args.get(1) {
+        let outcome = match rule.as_str() {
+            "increment" => run(&instructions, increment, Guard::Unbounded),
+            "stranger"  => run(&instructions, stranger, Guard::Unbounded),
+            _           => {
+                let rule = Expr::parse(rule).unwrap_or_else(|e| panic!("bad rule: {}", e));
+                // Hashing whole states only pays off while the offset table is tiny.
+                let guard = if instructions.len() <= 64 { Guard::States } else { Guard::Steps(1 << 32) };
+                run(&instructions, |o| rule.eval(o), guard)
+            },
+        };
+        println!("{:?}", outcome);
+        return
+    }
     println!("{}", execute(&instructions));
     println!("{}", execute_b(&instructions));
 }
@@ -46,3 +404,112 @@ fn execute_test() {
 fn execute_b_test() {
     assert_eq!(10, execute_b(&[0, 3, 0, 1, -3]));
 }
+
+#[test]
+fn run_test() {
+    assert_eq!(Outcome::Exited{steps: 5}, run(&[0, 3, 0, 1, -3], increment, Guard::States));
+    assert_eq!(Outcome::Exited{steps: 10}, run(&[0, 3, 0, 1, -3], stranger, Guard::Steps(10)));
+    assert_eq!(Outcome::GaveUp{steps: 9}, run(&[0, 3, 0, 1, -3], stranger, Guard::Steps(9)));
+    assert_eq!(Outcome::Overflowed{steps: 1, at: 0}, run(&[i32::MAX], increment, Guard::Unbounded));
+    assert_eq!(1, execute(&[i32::MAX]));
+    assert_eq!(Outcome::Exited{steps: 1}, run(&[-1], Some, Guard::States));
+}
+
+#[test]
+fn run_loop_test() {
+    assert_eq!(Outcome::Looped{steps: 1, period: 1}, run(&[0], Some, Guard::States));
+    assert_eq!(Outcome::Looped{steps: 2, period: 2}, run(&[1, -1], Some, Guard::States));
+    assert_eq!(Outcome::GaveUp{steps: 1000}, run(&[1, -1], Some, Guard::Steps(1000)));
+}
+
+#[test]
+fn expr_test() {
+    let part_b = Expr::parse("o >= 3 ? o - 1 : o + 1").unwrap();
+    for o in -5..10 {
+        assert_eq!(stranger(o), part_b.eval(o));
+    }
+    assert_eq!(Outcome::Exited{steps: 10}, run(&[0, 3, 0, 1, -3], |o| part_b.eval(o), Guard::Unbounded));
+    assert_eq!(Some(7), Expr::parse("1 + 2 * 3").unwrap().eval(0));
+    assert_eq!(Some(-4), Expr::parse("-(o + 1) * 2").unwrap().eval(1));
+    assert_eq!(Some(3), Expr::parse("o < 0 ? 1 : o == 0 ? 2 : 3").unwrap().eval(5));
+    assert_eq!(Err("unexpected 'x'".to_owned()), Expr::parse("o + x"));
+    assert_eq!(Err("expected ':'".to_owned()), Expr::parse("o > 1 ? 2"));
+    assert_eq!(Err("unexpected ')'".to_owned()), Expr::parse("o)"));
+}
+
+#[test]
+fn expr_overflow_test() {
+    let rule = Expr::parse("o * 100000").unwrap();
+    assert_eq!(None, rule.eval(100000));
+    assert_eq!(None, Expr::parse("-o").unwrap().eval(i32::MIN));
+    assert_eq!(Outcome::Overflowed{steps: 1, at: 0}, run(&[100000, 1], |o| rule.eval(o), Guard::Unbounded));
+    assert_eq!(Outcome::Exited{steps: 2}, run(&[1, 2], |o| rule.eval(o), Guard::Unbounded));
+}
+
+// Every program of up to `len` instructions with offsets in -3..=3.
+#[cfg(test)]
+fn programs(len: usize) -> Vec<Vec<i32>> {
+    let mut all = vec![vec![]];
+    let mut last = vec![vec![]];
+    for _ in 0..len {
+        last = last.iter().flat_map(|p: &Vec<i32>| (-3..=3).map(move |o| {
+            let mut p = p.clone();
+            p.push(o);
+            p
+        })).collect();
+        all.extend(last.iter().cloned());
+    }
+    all
+}
+
+// Instruction i jumps back to the start until its offset has counted up
+// past zero, so the prefix, long since settled into 2s and 3s, is walked
+// over and over: the step count grows with the cube of the length.
+#[cfg(test)]
+fn back_to_start(len: usize) -> Vec<i32> {
+    (0..len as i32).map(|i| -i).collect()
+}
+
+#[cfg(test)]
+fn run_steps(instr: &[i32], update: fn(i32) -> Option<i32>) -> u64 {
+    match run(instr, update, Guard::Unbounded) {
+        Outcome::Exited{steps}  => steps,
+        other                   => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn execute_matches_run_test() {
+    for instr in programs(5).into_iter().chain((1..80).map(back_to_start)) {
+        assert_eq!(run_steps(&instr, increment), execute(&instr), "{:?}", instr);
+        assert_eq!(run_steps(&instr, stranger), execute_b(&instr), "{:?}", instr);
+    }
+    assert_eq!(2, execute(&[1, 7]));
+    assert_eq!(1, execute_b(&[-1]));
+}
+
+#[test]
+fn execute_b_long_prefix_test() {
+    let instr = back_to_start(400);
+    let steps = run_steps(&instr, stranger);
+    assert!(steps > 1_000_000);
+    assert_eq!(steps, execute_b(&instr));
+}
+
+#[test]
+fn profile_test() {
+    let p = execute_profile(&[0, 3, 0, 1, -3]);
+    assert_eq!(Profile{steps: 5, visits: vec![2, 2, 0, 0, 1], offsets: vec![2, 5, 0, 1, -2], exit: Exit::Right, last: 1}, p);
+    let p = execute_b_profile(&[0, 3, 0, 1, -3]);
+    assert_eq!(Profile{steps: 10, visits: vec![2, 2, 2, 2, 2], offsets: vec![2, 3, 2, 3, -1], exit: Exit::Right, last: 3}, p);
+    assert_eq!(Exit::Left, execute_profile(&[1, -2]).exit);
+    assert_eq!(1, execute_profile(&[1, -2]).last);
+    let p = execute_profile(&[1, i32::MAX]);
+    assert_eq!(Profile{steps: 2, visits: vec![1, 1], offsets: vec![2, i32::MAX], exit: Exit::Overflowed, last: 1}, p);
+}
+
+#[test]
+fn profile_csv_test() {
+    let csv = execute_profile(&[1, -2]).to_csv();
+    assert_eq!("index,visits,final_offset,last\n0,1,2,0\n1,1,-1,1\n", csv);
+}
@@ -1,17 +1,19 @@
-use std::io::{BufRead,BufReader};
+use std::io::Read;
 use std::collections::HashMap;
 
-fn distribute(mut amount: i32, start: usize, mut mem: Vec<i32>) -> Vec<i32> {
-    let mut pos = start;
-    while amount > 0 {
-        mem[pos] += 1;
-        amount -= 1;
-        pos = (pos + 1) % mem.len();
+// Every bank gets an equal share and the remainder goes one block each to
+// the banks following `start`, wrapping around.
+fn distribute(amount: u64, start: usize, mut mem: Vec<u64>) -> Vec<u64> {
+    let n = mem.len();
+    let (share, extra) = (amount / n as u64, (amount % n as u64) as usize);
+    for (i, bank) in mem.iter_mut().enumerate() {
+        let from_start = (i + n - start) % n;
+        *bank += share + if from_start < extra { 1 } else { 0 };
     }
     mem
 }
 
-fn find_max_block(mem: &[i32]) -> usize {
+fn find_max_block(mem: &[u64]) -> usize {
     mem.iter()
         .enumerate()
         .rev()
@@ -19,11 +21,26 @@ fn find_max_block(mem: &[i32]) -> usize {
         .unwrap().0
 }
 
-fn solve(mut mem: Vec<i32>) -> (usize, usize) {
+#[derive(Debug,PartialEq)]
+struct Report {
+    // Cycles until a configuration repeats.
+    steps: usize,
+    loop_size: usize,
+    repeated: Vec<u64>,
+    first_seen: usize,
+    // Configurations from `first_seen` up to just before the repeat.
+    states: Option<Vec<Vec<u64>>>,
+}
+
+fn reallocate(mut mem: Vec<u64>, keep_states: bool) -> Report {
     let mut seen = HashMap::new();
+    let mut history = vec![];
     let mut c = 0;
     seen.insert(mem.clone(), c);
     loop {
+        if keep_states {
+            history.push(mem.clone());
+        }
         c += 1;
         let next = find_max_block(&mem);
 
@@ -32,20 +49,41 @@ fn solve(mut mem: Vec<i32>) -> (usize, usize) {
         let start = (next + 1) % mem.len();
         mem = distribute(todo, start, mem);
         if let Some(old) = seen.insert(mem.clone(), c) {
-            return (c, c - old)
+            let states = if keep_states { Some(history.split_off(old)) } else { None };
+            return Report{steps: c, loop_size: c - old, repeated: mem, first_seen: old, states}
         }
     }
 }
 
-fn main() {
-    let mem : Vec<i32> = BufReader::new(std::io::stdin())
-        .lines()
-        .nth(0)
-        .unwrap()
-        .unwrap()
-        .split("\t")
-        .map(|s| s.parse::<i32>().unwrap())
+fn solve(mem: Vec<u64>) -> (usize, usize) {
+    let r = reallocate(mem, false);
+    (r.steps, r.loop_size)
+}
+
+fn parse(input: &str) -> Result<Vec<u64>, String> {
+    let mem : Result<Vec<u64>, String> = input
+        .split_whitespace()
+        .enumerate()
+        .map(|(i, s)| s.parse::<u64>().map_err(|_| format!("bank {}: '{}' is not a block count", i, s)))
         .collect();
+    match mem {
+        Ok(ref m) if m.is_empty()   => Err("no banks".to_owned()),
+        other                       => other,
+    }
+}
+
+fn main() {
+    let mut input = String::new();
+    std::io::stdin().read_to_string(&mut input).unwrap();
+    let mem = parse(&input).unwrap_or_else(|e| panic!("{}", e));
+    if std::env::args().nth(1).map(|a| a == "--states").unwrap_or(false) {
+        let r = reallocate(mem, true);
+        println!("{} {} first seen at {}: {:?}", r.steps, r.loop_size, r.first_seen, r.repeated);
+        for s in r.states.unwrap() {
+            println!("{:?}", s);
+        }
+        return
+    }
     let s = solve(mem);
     println!("{}", s.0);
     println!("{}", s.1);
@@ -67,3 +105,32 @@ fn find_next_block_test() {
 fn solve_test() {
     assert_eq!((5, 4), solve(vec![0, 2, 7, 0]));
 }
+
+#[test]
+fn reallocate_test() {
+    assert_eq!(Report{steps: 5, loop_size: 4, repeated: vec![2, 4, 1, 2], first_seen: 1, states: None},
+               reallocate(vec![0, 2, 7, 0], false));
+    let states = reallocate(vec![0, 2, 7, 0], true).states.unwrap();
+    assert_eq!(vec![vec![2, 4, 1, 2], vec![3, 1, 2, 3], vec![0, 2, 3, 4], vec![1, 3, 4, 1]], states);
+}
+
+#[test]
+fn parse_test() {
+    assert_eq!(Ok(vec![0, 2, 7, 0]), parse("0\t2 7\n  0\n"));
+    assert_eq!(Ok(vec![10_000_000_000]), parse("10000000000"));
+    assert_eq!(Err("bank 1: '-2' is not a block count".to_owned()), parse("0 -2"));
+    assert_eq!(Err("no banks".to_owned()), parse(" \n"));
+}
+
+#[test]
+fn distribute_huge_test() {
+    assert_eq!(vec![333_333_334, 333_333_333, 333_333_335], distribute(1_000_000_001, 2, vec![0, 0, 1]));
+    assert_eq!(vec![1 << 62; 4], distribute(u64::MAX - 3, 1, vec![1, 1, 1, 1]));
+    assert_eq!((42, 3), solve(vec![0, 3_000_000_000, 0]));
+}
+
+#[test]
+fn find_max_block_tie_test() {
+    assert_eq!(0, find_max_block(&[9, 9, 9]));
+    assert_eq!(1, find_max_block(&[1, 9, 2, 9]));
+}
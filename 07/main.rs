@@ -1,106 +1,522 @@
 use std::io::{BufRead, BufReader};
 use std::collections::{HashMap,HashSet};
+use std::fmt::Write;
 
 #[derive(Debug,Clone)]
 struct Prog {
     name: String,
     weight: i32, 
-    total_weight: i32,
     children: Vec<String>,
 }
 
-impl Prog {
-    fn is_childless(&self) -> bool {
-        self.children.is_empty()
+// Lines are 1-based, as an editor would show them.
+#[derive(Debug,PartialEq)]
+enum ParseError {
+    Empty{line: usize},
+    BadWeight{line: usize, text: String},
+    ExpectedArrow{line: usize, text: String},
+    BadChildren{line: usize},
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            ParseError::Empty{line}                 => write!(f, "line {}: no program", line),
+            ParseError::BadWeight{line, ref text}   => write!(f, "line {}: '{}' is not a weight like (42)", line, text),
+            ParseError::ExpectedArrow{line, ref text} => write!(f, "line {}: expected '->', found '{}'", line, text),
+            ParseError::BadChildren{line}           => write!(f, "line {}: '->' needs a comma separated list of programs", line),
+        }
     }
 }
 
-fn parse(input: &str) -> Prog {
+fn parse(input: &str, line: usize) -> Result<Prog, ParseError> {
     let split : Vec<&str> = input.split_whitespace().collect();
-    let name = split[0].to_owned();
-    let weight = split[1][1..split[1].len()-1].parse::<i32>().unwrap();
-    let children = if split.len() == 2 { 
-        vec![] 
-    } else { 
-        split[3..].iter().map(|s| (*s).to_owned().replace(",","")).collect()
+    let name = split.first().ok_or(ParseError::Empty{line})?.to_string();
+    let weight = split.get(1)
+        .and_then(|w| if w.starts_with('(') && w.ends_with(')') { w[1..w.len()-1].parse::<i32>().ok() } else { None })
+        .ok_or_else(|| ParseError::BadWeight{line, text: split.get(1).cloned().unwrap_or("").to_owned()})?;
+    let children = match split.get(2) {
+        None        => vec![],
+        Some(&"->") => {
+            let children : Vec<String> = split[3..].join(" ").split(',').map(|c| c.trim().to_owned()).collect();
+            if children.iter().any(|c| c.is_empty() || c.contains(' ')) {
+                return Err(ParseError::BadChildren{line})
+            }
+            children
+        },
+        Some(text)  => return Err(ParseError::ExpectedArrow{line, text: text.to_string()}),
     };
-    let total_weight = weight;
-    Prog{name, weight, total_weight, children}
+    Ok(Prog{name, weight, children})
+}
+
+#[derive(Debug)]
+struct Node {
+    name: String,
+    weight: i32,
+    total_weight: i32,
+    parent: Option<usize>,
+    children: Vec<usize>,
+}
+
+// Programs stored by index, with the total weight of every subtree
+// computed once while building.
+#[derive(Debug)]
+struct Tower {
+    nodes: Vec<Node>,
+    root: usize,
+}
+
+#[derive(Debug,PartialEq)]
+enum TowerError {
+    DuplicateProgram(String),
+    DanglingChild{parent: String, child: String},
+    DuplicateChild{parent: String, child: String},
+    MultipleParents{child: String, parents: Vec<String>},
+    MultipleRoots(Vec<String>),
+    Cycle(Vec<String>),
+    Empty,
+}
+
+impl std::fmt::Display for TowerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            TowerError::DuplicateProgram(ref name) =>
+                write!(f, "program {} is listed more than once", name),
+            TowerError::DanglingChild{ref parent, ref child} =>
+                write!(f, "{} holds {}, which is not listed", parent, child),
+            TowerError::DuplicateChild{ref parent, ref child} =>
+                write!(f, "{} holds {} more than once", parent, child),
+            TowerError::MultipleParents{ref child, ref parents} =>
+                write!(f, "{} is held by {}", child, parents.join(", ")),
+            TowerError::MultipleRoots(ref roots) =>
+                write!(f, "more than one bottom program: {}", roots.join(", ")),
+            TowerError::Cycle(ref names) =>
+                write!(f, "programs holding each other up: {}", names.join(", ")),
+            TowerError::Empty =>
+                write!(f, "no programs listed"),
+        }
+    }
 }
 
-fn solve_a(progs: &[Prog]) -> &str {
-    let mut parent = HashMap::new();
-    for p in progs {
-        for h in &p.children {
-            parent.insert(h, p.name.clone());
+impl Tower {
+    fn build(progs: &[Prog]) -> Result<Tower, TowerError> {
+        if progs.is_empty() {
+            return Err(TowerError::Empty)
+        }
+        let mut index = HashMap::new();
+        for (i, p) in progs.iter().enumerate() {
+            if index.insert(p.name.as_str(), i).is_some() {
+                return Err(TowerError::DuplicateProgram(p.name.clone()))
+            }
+        }
+        let mut nodes : Vec<Node> = progs.iter()
+            .map(|p| Node{name: p.name.clone(), weight: p.weight, total_weight: p.weight, parent: None, children: vec![]})
+            .collect();
+        let mut parents : Vec<Vec<usize>> = vec![vec![]; progs.len()];
+        for (i, p) in progs.iter().enumerate() {
+            let mut held = HashSet::new();
+            for c in &p.children {
+                let child = match index.get(c.as_str()) {
+                    Some(&child)    => child,
+                    None            => return Err(TowerError::DanglingChild{parent: p.name.clone(), child: c.clone()}),
+                };
+                if !held.insert(child) {
+                    return Err(TowerError::DuplicateChild{parent: p.name.clone(), child: c.clone()})
+                }
+                parents[child].push(i);
+                nodes[i].children.push(child);
+            }
+        }
+        for (i, ps) in parents.iter().enumerate() {
+            if ps.len() > 1 {
+                let names = ps.iter().map(|&p| progs[p].name.clone()).collect();
+                return Err(TowerError::MultipleParents{child: progs[i].name.clone(), parents: names})
+            }
+            nodes[i].parent = ps.first().cloned();
+        }
+
+        let roots : Vec<usize> = (0..nodes.len()).filter(|&i| nodes[i].parent.is_none()).collect();
+        if roots.len() > 1 {
+            return Err(TowerError::MultipleRoots(roots.iter().map(|&r| nodes[r].name.clone()).collect()))
         }
+        // With one parent each, whatever the root cannot reach is a cycle.
+        let mut order = roots.clone();
+        let mut i = 0;
+        while i < order.len() {
+            order.extend(nodes[order[i]].children.iter().cloned());
+            i += 1;
+        }
+        if roots.is_empty() || order.len() < nodes.len() {
+            let reached : HashSet<usize> = order.iter().cloned().collect();
+            let mut names : Vec<String> = (0..nodes.len())
+                .filter(|i| !reached.contains(i))
+                .map(|i| nodes[i].name.clone())
+                .collect();
+            names.sort();
+            return Err(TowerError::Cycle(names))
+        }
+        for &n in order.iter().rev() {
+            if let Some(p) = nodes[n].parent {
+                nodes[p].total_weight += nodes[n].total_weight;
+            }
+        }
+        Ok(Tower{nodes, root: roots[0]})
     }
-    &progs.iter().find(|p| !parent.contains_key(&p.name)).unwrap().name
 }
 
-fn get_all_parents_of_childless(progs: &HashMap<String, Prog>) -> HashSet<String> {
-    progs.iter()
-        .filter(|&(_, prog)| !prog.is_childless())
-        .filter(|&(_, prog)| prog.children.iter().all(|c| progs.get(c).unwrap().is_childless()))
-        .map(|(name, _)| name.to_owned())
-        .collect()
+fn solve_a(tower: &Tower) -> &str {
+    &tower.nodes[tower.root].name
+}
+
+#[derive(Debug,PartialEq)]
+enum Fix {
+    Weight{program: String, from: i32, to: i32},
+    // No total weight is shared by more children than every other one, e.g.
+    // when there are only two of them, so any one could be the wrong one.
+    Ambiguous{candidates: Vec<String>},
+}
+
+#[derive(Debug,PartialEq)]
+struct Imbalance {
+    // From the bottom program up to the one whose children disagree.
+    path: Vec<String>,
+    // Children with their total weights, after fixes further up the tower.
+    siblings: Vec<(String, i32)>,
+    fixes: Vec<Fix>,
 }
 
-fn has_unbalanced_children(node: &String, progs: &HashMap<String, Prog>) -> Option<Vec<(i32,i32)>> {
-    let mut weights = HashSet::new();
-    let mut all_weights = vec![];
-    for child in &progs.get(node).unwrap().children {
-        let child_prog = progs.get(child).unwrap();
-        weights.insert(child_prog.total_weight);
-        all_weights.push((child_prog.weight, child_prog.total_weight));
+impl std::fmt::Display for Fix {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Fix::Weight{ref program, from, to}  => write!(f, "{} should weigh {}, not {}", program, to, from),
+            Fix::Ambiguous{ref candidates}      => write!(f, "any of {} could be off", candidates.join(", ")),
+        }
     }
-    if weights.len() == 1 { None } else { Some(all_weights) }
 }
 
-fn find_correct_weight(weights: &[(i32, i32)]) -> i32 {
-    let mut m = HashMap::new();
-    for &(_, total) in weights {
-        *m.entry(total).or_insert(0) += 1;
+impl std::fmt::Display for Imbalance {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let siblings : Vec<String> = self.siblings.iter().map(|&(ref name, total)| format!("{} {}", name, total)).collect();
+        let fixes : Vec<String> = self.fixes.iter().map(|fix| fix.to_string()).collect();
+        write!(f, "{}: {}; {}", self.path.join(" -> "), siblings.join(", "), fixes.join("; "))
     }
-    debug_assert!(m.len() == 2);
-    let max = m.iter().max_by_key(|&(_, c)| c).unwrap();
-    let min = m.iter().min_by_key(|&(_, c)| c).unwrap();
-    let diff = max.0 - min.0;
-    for &(local, total) in weights {
-        if *min.0 == total {
-            return local + diff;
+}
+
+impl Tower {
+    fn path(&self, mut node: usize) -> Vec<String> {
+        let mut path = vec![self.nodes[node].name.clone()];
+        while let Some(p) = self.nodes[node].parent {
+            path.push(self.nodes[p].name.clone());
+            node = p;
         }
+        path.reverse();
+        path
     }
-    unreachable!();
 }
 
-fn solve_b(progs : Vec<Prog>) -> i32 {
-    let mut progs_map = HashMap::new();
-    for p in &progs {
-        progs_map.insert(p.name.clone(), p.clone());
+// Works from the top of the tower down, so once a program is fixed its
+// corrected total is what its parent sees, and an imbalance is only
+// reported where it is not explained by one further up.
+fn imbalances(tower: &Tower) -> Vec<Imbalance> {
+    let mut order = vec![tower.root];
+    let mut i = 0;
+    while i < order.len() {
+        order.extend(tower.nodes[order[i]].children.iter().cloned());
+        i += 1;
+    }
+    let mut totals : Vec<i32> = tower.nodes.iter().map(|n| n.weight).collect();
+    let mut found = vec![];
+    for &n in order.iter().rev() {
+        let children = &tower.nodes[n].children;
+        let mut counts : HashMap<i32, usize> = HashMap::new();
+        for &c in children {
+            *counts.entry(totals[c]).or_insert(0) += 1;
+        }
+        if counts.len() > 1 {
+            let siblings = children.iter().map(|&c| (tower.nodes[c].name.clone(), totals[c])).collect();
+            let most = *counts.values().max().unwrap();
+            let expected = match counts.iter().filter(|&(_, &c)| c == most).count() {
+                1   => counts.iter().find(|&(_, &c)| c == most).map(|(&t, _)| t),
+                _   => None,
+            };
+            let mut fixes = vec![];
+            match expected {
+                Some(expected) => for &c in children {
+                    if totals[c] != expected {
+                        let from = tower.nodes[c].weight;
+                        let to = from + expected - totals[c];
+                        fixes.push(Fix::Weight{program: tower.nodes[c].name.clone(), from, to});
+                        totals[c] = expected;
+                    }
+                },
+                None => fixes.push(Fix::Ambiguous{candidates: children.iter().map(|&c| tower.nodes[c].name.clone()).collect()}),
+            }
+            found.push(Imbalance{path: tower.path(n), siblings, fixes});
+        }
+        let sum : i32 = children.iter().map(|&c| totals[c]).sum();
+        totals[n] += sum;
     }
+    found
+}
+
+fn solve_b(tower: &Tower) -> Option<i32> {
+    imbalances(tower).into_iter().flat_map(|i| i.fixes).filter_map(|f| match f {
+        Fix::Weight{to, ..}     => Some(to),
+        Fix::Ambiguous{..}      => None,
+    }).next()
+}
+
+// Programs whose children disagree, and every program in the subtrees the
+// fixes point at.
+fn highlights(tower: &Tower) -> (HashSet<usize>, HashSet<usize>) {
+    let index : HashMap<&str, usize> = tower.nodes.iter().enumerate().map(|(i, n)| (n.name.as_str(), i)).collect();
+    let mut unbalanced = HashSet::new();
+    let mut suspect = HashSet::new();
+    for i in imbalances(tower) {
+        unbalanced.insert(index[i.path.last().unwrap().as_str()]);
+        for f in i.fixes {
+            let names = match f {
+                Fix::Weight{program, ..}        => vec![program],
+                Fix::Ambiguous{candidates}      => candidates,
+            };
+            let mut todo : Vec<usize> = names.iter().map(|n| index[n.as_str()]).collect();
+            while let Some(n) = todo.pop() {
+                suspect.insert(n);
+                todo.extend(tower.nodes[n].children.iter().cloned());
+            }
+        }
+    }
+    (unbalanced, suspect)
+}
+
+fn to_dot(tower: &Tower) -> String {
+    let (unbalanced, suspect) = highlights(tower);
+    let mut out = "digraph tower {\n    rankdir=BT;\n".to_owned();
+    for (i, n) in tower.nodes.iter().enumerate() {
+        let style = if unbalanced.contains(&i) {
+            ", style=filled, fillcolor=orange"
+        } else if suspect.contains(&i) {
+            ", style=filled, fillcolor=tomato"
+        } else {
+            ""
+        };
+        writeln!(out, "    \"{}\" [label=\"{}\\n{} / {}\"{}];", n.name, n.name, n.weight, n.total_weight, style).unwrap();
+    }
+    for n in &tower.nodes {
+        for &c in &n.children {
+            writeln!(out, "    \"{}\" -> \"{}\";", tower.nodes[c].name, n.name).unwrap();
+        }
+    }
+    out + "}\n"
+}
 
-    loop {
-        let parents_to_check = get_all_parents_of_childless(&progs_map);
-        for parent in &parents_to_check {
-            if let Some(weights) = has_unbalanced_children(&parent, &progs_map) {
-                return find_correct_weight(&weights);
+// Programs deeper than `max_depth` are folded into a count on their
+// ancestor at that depth; unbalanced programs are marked with `!`.
+fn to_text(tower: &Tower, max_depth: Option<usize>) -> String {
+    let (unbalanced, _) = highlights(tower);
+    let mut out = String::new();
+    let mut todo = vec![(tower.root, 0, String::new(), true)];
+    while let Some((n, depth, prefix, last)) = todo.pop() {
+        let node = &tower.nodes[n];
+        let branch = if depth == 0 { "" } else if last { "`-- " } else { "|-- " };
+        write!(out, "{}{}{} ({}", prefix, branch, node.name, node.weight).unwrap();
+        if !node.children.is_empty() {
+            write!(out, ", total {}", node.total_weight).unwrap();
+        }
+        write!(out, ")").unwrap();
+        if unbalanced.contains(&n) {
+            write!(out, " !").unwrap();
+        }
+        if max_depth == Some(depth) && !node.children.is_empty() {
+            let mut hidden = 0;
+            let mut below = node.children.clone();
+            while let Some(c) = below.pop() {
+                hidden += 1;
+                below.extend(tower.nodes[c].children.iter().cloned());
             }
+            write!(out, " [+{}]", hidden).unwrap();
         }
-        for parent in parents_to_check {
-            let mut p = progs_map.remove(&parent).unwrap().clone();
-            p.total_weight += p.children
-                .into_iter()
-                .map(|c| progs_map.remove(&c).unwrap().total_weight)
-                .sum();
-            p.children = vec![];
-            progs_map.insert(parent, p);
+        writeln!(out).unwrap();
+        if max_depth == Some(depth) {
+            continue
+        }
+        let child_prefix = if depth == 0 { prefix } else if last { prefix + "    " } else { prefix + "|   " };
+        for (i, &c) in node.children.iter().enumerate().rev() {
+            todo.push((c, depth + 1, child_prefix.clone(), i + 1 == node.children.len()));
         }
     }
+    out
 }
 
 fn main() {
-    let progs : Vec<Prog> = BufReader::new(std::io::stdin()).lines().map(|l| parse(&l.unwrap())).collect();
-    println!("{}", solve_a(&progs));
-    println!("{}", solve_b(progs));
+    let progs : Result<Vec<Prog>, ParseError> = BufReader::new(std::io::stdin()).lines()
+        .enumerate()
+        .map(|(i, l)| parse(&l.unwrap(), i + 1))
+        .collect();
+    let tower = progs.map_err(|e| e.to_string())
+        .and_then(|progs| Tower::build(&progs).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| { eprintln!("{}", e); std::process::exit(1) });
+    let args : Vec<String> = std::env::args().collect();
+    match args.get(1).map(|s| s.as_str()) {
+        Some("dot")     => { print!("{}", to_dot(&tower)); return },
+        Some("tree")    => { print!("{}", to_text(&tower, args.get(2).map(|d| d.parse().unwrap()))); return },
+        Some("imbalances") => {
+            for i in imbalances(&tower) {
+                println!("{}", i);
+            }
+            return
+        },
+        _               => {},
+    }
+    println!("{}", solve_a(&tower));
+    match solve_b(&tower) {
+        Some(w) => println!("{}", w),
+        None    => println!("balanced"),
+    }
+}
+
+#[cfg(test)]
+const EXAMPLE: &str = "pbga (66)
+xhth (57)
+ebii (61)
+havc (66)
+ktlj (57)
+fwft (72) -> ktlj, cntj, xhth
+qoyq (66)
+padx (45) -> pbga, havc, qoyq
+tknk (41) -> ugml, padx, fwft
+jptl (61)
+ugml (68) -> gyxo, ebii, jptl
+gyxo (61)
+cntj (57)";
+
+#[cfg(test)]
+fn tower(input: &str) -> Result<Tower, TowerError> {
+    let progs : Vec<Prog> = input.lines().enumerate().map(|(i, l)| parse(l, i + 1).unwrap()).collect();
+    Tower::build(&progs)
+}
+
+#[test]
+fn build_test() {
+    let t = tower(EXAMPLE).unwrap();
+    assert_eq!("tknk", solve_a(&t));
+    assert_eq!(778, t.nodes[t.root].total_weight);
+    let ugml = t.nodes.iter().position(|n| n.name == "ugml").unwrap();
+    assert_eq!(251, t.nodes[ugml].total_weight);
+    assert_eq!(Some(t.root), t.nodes[ugml].parent);
+    assert_eq!(Some(60), solve_b(&t));
+}
+
+#[test]
+fn build_error_test() {
+    assert_eq!(Some(TowerError::DuplicateProgram("a".to_owned())),
+               tower("a (1)\na (2)").err());
+    assert_eq!(Some(TowerError::DanglingChild{parent: "a".to_owned(), child: "c".to_owned()}),
+               tower("a (1) -> b, c\nb (1)").err());
+    assert_eq!(Some(TowerError::DuplicateChild{parent: "a".to_owned(), child: "b".to_owned()}),
+               tower("a (1) -> b, b\nb (1)").err());
+    assert_eq!(Some(TowerError::MultipleParents{child: "c".to_owned(), parents: vec!["a".to_owned(), "b".to_owned()]}),
+               tower("a (1) -> c\nb (1) -> c\nc (1)").err());
+    assert_eq!(Some(TowerError::MultipleRoots(vec!["a".to_owned(), "b".to_owned()])),
+               tower("a (1)\nb (1)").err());
+    assert_eq!(Some(TowerError::Cycle(vec!["b".to_owned(), "c".to_owned()])),
+               tower("a (1)\nb (1) -> c\nc (1) -> b").err());
+    assert_eq!(Some(TowerError::Cycle(vec!["a".to_owned()])),
+               tower("a (1) -> a").err());
+    assert_eq!("c is held by a, b",
+               tower("a (1) -> c\nb (1) -> c\nc (1)").unwrap_err().to_string());
+    assert_eq!(Some(TowerError::Empty), tower("").err());
+}
+
+#[test]
+fn parse_test() {
+    assert_eq!(vec!["ktlj", "cntj", "xhth"], parse("fwft (72) -> ktlj, cntj, xhth", 1).unwrap().children);
+    assert_eq!(72, parse("fwft (72)", 1).unwrap().weight);
+    assert_eq!(Some(ParseError::Empty{line: 3}), parse("  ", 3).err());
+    assert_eq!(Some(ParseError::BadWeight{line: 1, text: "".to_owned()}), parse("fwft", 1).err());
+    assert_eq!(Some(ParseError::BadWeight{line: 2, text: "72".to_owned()}), parse("fwft 72", 2).err());
+    assert_eq!(Some(ParseError::BadWeight{line: 2, text: "(x)".to_owned()}), parse("fwft (x)", 2).err());
+    assert_eq!(Some(ParseError::ExpectedArrow{line: 4, text: "=>".to_owned()}), parse("fwft (72) => ktlj", 4).err());
+    assert_eq!(Some(ParseError::BadChildren{line: 5}), parse("fwft (72) ->", 5).err());
+    assert_eq!(Some(ParseError::BadChildren{line: 5}), parse("fwft (72) -> ktlj,, cntj", 5).err());
+    assert_eq!(Some(ParseError::BadChildren{line: 5}), parse("fwft (72) -> ktlj cntj", 5).err());
+    assert_eq!("line 2: '72' is not a weight like (42)", parse("fwft 72", 2).unwrap_err().to_string());
+}
+
+#[test]
+fn imbalances_test() {
+    let t = tower(EXAMPLE).unwrap();
+    let siblings = vec![("ugml".to_owned(), 251), ("padx".to_owned(), 243), ("fwft".to_owned(), 243)];
+    assert_eq!(vec![Imbalance{
+        path: vec!["tknk".to_owned()],
+        siblings,
+        fixes: vec![Fix::Weight{program: "ugml".to_owned(), from: 68, to: 60}],
+    }], imbalances(&t));
+    assert_eq!(Vec::<Imbalance>::new(), imbalances(&tower("a (1) -> b, c\nb (2)\nc (2)").unwrap()));
+    assert_eq!("tknk: ugml 251, padx 243, fwft 243; ugml should weigh 60, not 68", imbalances(&t)[0].to_string());
+}
+
+#[test]
+fn imbalances_ambiguous_test() {
+    let t = tower("a (1) -> b, c\nb (2)\nc (3)").unwrap();
+    assert_eq!(vec![Imbalance{
+        path: vec!["a".to_owned()],
+        siblings: vec![("b".to_owned(), 2), ("c".to_owned(), 3)],
+        fixes: vec![Fix::Ambiguous{candidates: vec!["b".to_owned(), "c".to_owned()]}],
+    }], imbalances(&t));
+    assert_eq!(None, solve_b(&t));
+    assert_eq!("a: b 2, c 3; any of b, c could be off", imbalances(&t)[0].to_string());
+}
+
+#[test]
+fn imbalances_several_test() {
+    // d is too heavy under b, and c is too light on its own.
+    let t = tower("a (1) -> b, c, x\nb (1) -> d, e, f\nd (5)\ne (3)\nf (3)\nc (8)\nx (10)").unwrap();
+    let found = imbalances(&t);
+    assert_eq!(2, found.len());
+    assert_eq!(vec!["a".to_owned(), "b".to_owned()], found[0].path);
+    assert_eq!(vec![Fix::Weight{program: "d".to_owned(), from: 5, to: 3}], found[0].fixes);
+    assert_eq!(vec!["a".to_owned()], found[1].path);
+    assert_eq!(vec![Fix::Weight{program: "c".to_owned(), from: 8, to: 10}], found[1].fixes);
+    assert_eq!(vec![("b".to_owned(), 10), ("c".to_owned(), 8), ("x".to_owned(), 10)], found[1].siblings);
+
+    let t = tower("a (1) -> b, c, d, e\nb (4)\nc (5)\nd (4)\ne (3)").unwrap();
+    assert_eq!(vec![Fix::Weight{program: "c".to_owned(), from: 5, to: 4}, Fix::Weight{program: "e".to_owned(), from: 3, to: 4}],
+               imbalances(&t)[0].fixes);
+}
+
+#[test]
+fn to_text_test() {
+    let t = tower(EXAMPLE).unwrap();
+    let expected = [
+        "tknk (41, total 778) !",
+        "|-- ugml (68, total 251)",
+        "|   |-- gyxo (61)",
+        "|   |-- ebii (61)",
+        "|   `-- jptl (61)",
+        "|-- padx (45, total 243)",
+        "|   |-- pbga (66)",
+        "|   |-- havc (66)",
+        "|   `-- qoyq (66)",
+        "`-- fwft (72, total 243)",
+        "    |-- ktlj (57)",
+        "    |-- cntj (57)",
+        "    `-- xhth (57)",
+    ].join("\n") + "\n";
+    assert_eq!(expected, to_text(&t, None));
+    assert_eq!("tknk (41, total 778) ! [+12]\n", to_text(&t, Some(0)));
+    assert!(to_text(&t, Some(1)).contains("`-- fwft (72, total 243) [+3]\n"));
+}
+
+#[test]
+fn to_dot_test() {
+    let t = tower(EXAMPLE).unwrap();
+    let dot = to_dot(&t);
+    assert!(dot.starts_with("digraph tower {\n"));
+    assert!(dot.contains("    \"tknk\" [label=\"tknk\\n41 / 778\", style=filled, fillcolor=orange];\n"));
+    assert!(dot.contains("    \"ugml\" [label=\"ugml\\n68 / 251\", style=filled, fillcolor=tomato];\n"));
+    assert!(dot.contains("    \"gyxo\" [label=\"gyxo\\n61 / 61\", style=filled, fillcolor=tomato];\n"));
+    assert!(dot.contains("    \"padx\" [label=\"padx\\n45 / 243\"];\n"));
+    assert!(dot.contains("    \"ugml\" -> \"tknk\";\n"));
+    assert_eq!(12, dot.matches(" -> ").count());
 }
@@ -1,93 +1,1119 @@
-use std::io::{BufRead,BufReader,stdin};
+use std::io::{BufRead,BufReader,Write,stdin};
 use std::collections::HashMap;
+use std::fmt;
 
-fn to_rel_fn(input: &str) -> &'static Fn(&i32, &i32) -> bool {
-    match input {
-        ">"     => &i32::gt,
-        ">="    => &i32::ge,
-        "<"     => &i32::lt,
-        "<="    => &i32::le,
-        "=="    => &i32::eq,
-        "!="    => &i32::ne,
-        &_      => panic!("unknown rel: {}", input),
+#[derive(Clone,Copy,PartialEq,Eq,Hash,Debug)]
+enum Rel { Gt, Ge, Lt, Le, Eq, Ne }
+
+impl Rel {
+    fn eval(&self, l: i64, r: i64) -> bool {
+        match *self {
+            Rel::Gt => l > r,
+            Rel::Ge => l >= r,
+            Rel::Lt => l < r,
+            Rel::Le => l <= r,
+            Rel::Eq => l == r,
+            Rel::Ne => l != r,
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        match *self {
+            Rel::Gt => ">",
+            Rel::Ge => ">=",
+            Rel::Lt => "<",
+            Rel::Le => "<=",
+            Rel::Eq => "==",
+            Rel::Ne => "!=",
+        }
     }
 }
 
-struct Cond {
-    reg: String,
-    rel: &'static Fn(&i32, &i32) -> bool,
-    val: i32,
+impl std::str::FromStr for Rel {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Rel, ParseError> {
+        [Rel::Gt, Rel::Ge, Rel::Lt, Rel::Le, Rel::Eq, Rel::Ne].iter()
+            .find(|r| r.symbol() == s)
+            .cloned()
+            .ok_or_else(|| ParseError::UnknownRel(s.to_owned()))
+    }
+}
+
+// Registers are named by lowercase letters, anything else is a number.
+#[derive(Clone,PartialEq,Eq,Hash,Debug)]
+enum Operand {
+    Reg(String),
+    Val(i64),
+}
+
+impl Operand {
+    fn value(&self, regs: &mut Registers) -> i64 {
+        match *self {
+            Operand::Reg(ref r) => regs.get(r),
+            Operand::Val(v)     => v,
+        }
+    }
+
+    fn register(&self) -> Option<&String> {
+        match *self {
+            Operand::Reg(ref r) => Some(r),
+            Operand::Val(_)     => None,
+        }
+    }
+}
+
+impl std::str::FromStr for Operand {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Operand, ParseError> {
+        if !s.is_empty() && s.chars().all(|c| c.is_ascii_lowercase()) {
+            return Ok(Operand::Reg(s.to_owned()))
+        }
+        s.parse().map(Operand::Val).map_err(|_| ParseError::BadOperand(s.to_owned()))
+    }
+}
+
+// `not` binds tighter than `and`, which binds tighter than `or`.
+#[derive(Clone,PartialEq,Eq,Hash,Debug)]
+enum Cond {
+    Cmp(Operand, Rel, Operand),
+    Not(Box<Cond>),
+    And(Box<Cond>, Box<Cond>),
+    Or(Box<Cond>, Box<Cond>),
 }
 
 impl Cond {
     fn eval(&self, regs: &mut Registers) -> bool {
-        (self.rel)(&regs.get(&self.reg), &self.val)
+        match *self {
+            Cond::Cmp(ref l, rel, ref r) => {
+                let (l, r) = (l.value(regs), r.value(regs));
+                rel.eval(l, r)
+            },
+            Cond::Not(ref c)        => !c.eval(regs),
+            Cond::And(ref l, ref r) => l.eval(regs) && r.eval(regs),
+            Cond::Or(ref l, ref r)  => l.eval(regs) || r.eval(regs),
+        }
+    }
+
+    // Every register the condition may read, and so bring into existence.
+    fn registers<'a>(&'a self, out: &mut Vec<&'a String>) {
+        match *self {
+            Cond::Cmp(ref l, _, ref r) => out.extend(l.register().into_iter().chain(r.register())),
+            Cond::Not(ref c)        => c.registers(out),
+            Cond::And(ref l, ref r) => { l.registers(out); r.registers(out) },
+            Cond::Or(ref l, ref r)  => { l.registers(out); r.registers(out) },
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match *self {
+            Cond::Or(..)    => 0,
+            Cond::And(..)   => 1,
+            _               => 2,
+        }
     }
 }
 
-enum Op {
-    Inc(i32),
-    Dec(i32),
+#[derive(Clone,Copy,PartialEq,Eq,Hash,Debug)]
+enum OpKind { Inc, Dec, Mul, Div, Mod, Set }
+
+impl OpKind {
+    fn name(&self) -> &'static str {
+        match *self {
+            OpKind::Inc => "inc",
+            OpKind::Dec => "dec",
+            OpKind::Mul => "mul",
+            OpKind::Div => "div",
+            OpKind::Mod => "mod",
+            OpKind::Set => "set",
+        }
+    }
+
+    fn apply(&self, l: i64, r: i64) -> Option<i64> {
+        match *self {
+            OpKind::Inc => l.checked_add(r),
+            OpKind::Dec => l.checked_sub(r),
+            OpKind::Mul => l.checked_mul(r),
+            OpKind::Div => l.checked_div(r),
+            OpKind::Mod => l.checked_rem(r),
+            OpKind::Set => Some(r),
+        }
+    }
 }
 
+impl std::str::FromStr for OpKind {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<OpKind, ParseError> {
+        [OpKind::Inc, OpKind::Dec, OpKind::Mul, OpKind::Div, OpKind::Mod, OpKind::Set].iter()
+            .find(|k| k.name() == s)
+            .cloned()
+            .ok_or_else(|| ParseError::UnknownOp(s.to_owned()))
+    }
+}
+
+#[derive(Clone,PartialEq,Eq,Hash,Debug)]
+struct Op {
+    kind: OpKind,
+    amount: Operand,
+}
+
+#[derive(Clone,PartialEq,Eq,Hash,Debug)]
 struct Instr {
     reg: String,
     op: Op,
     cond: Cond,
 }
 
+#[derive(Debug,PartialEq)]
+enum ExecError {
+    DivideByZero(String),
+    Overflow(String),
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ExecError::DivideByZero(ref r)  => write!(f, "division by zero in register {}", r),
+            ExecError::Overflow(ref r)      => write!(f, "register {} overflowed", r),
+        }
+    }
+}
+
 impl Instr {
-    fn exec(&self, registers: &mut Registers) {
-        if self.cond.eval(registers) {
-            let next = registers.get(&self.reg) + match self.op {
-                Op::Inc(v) => v,
-                Op::Dec(v) => -v,
-            };
-            registers.set(&self.reg, next);
+    // Whether the instruction fired, i.e. its condition held.
+    fn exec(&self, registers: &mut Registers) -> Result<bool, ExecError> {
+        if !self.cond.eval(registers) {
+            registers.fired(false);
+            return Ok(false)
+        }
+        let amount = self.op.amount.value(registers);
+        if amount == 0 && (self.op.kind == OpKind::Div || self.op.kind == OpKind::Mod) {
+            return Err(ExecError::DivideByZero(self.reg.clone()))
+        }
+        let current = registers.get(&self.reg);
+        let next = self.op.kind.apply(current, amount).ok_or_else(|| ExecError::Overflow(self.reg.clone()))?;
+        registers.set(&self.reg, next);
+        registers.fired(true);
+        Ok(true)
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operand::Reg(ref r) => write!(f, "{}", r),
+            Operand::Val(v)     => write!(f, "{}", v),
+        }
+    }
+}
+
+impl fmt::Display for Cond {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operand = |f: &mut fmt::Formatter, c: &Cond, min: u8| if c.precedence() < min {
+            write!(f, "({})", c)
+        } else {
+            write!(f, "{}", c)
+        };
+        match *self {
+            Cond::Cmp(ref l, rel, ref r) => write!(f, "{} {} {}", l, rel.symbol(), r),
+            Cond::Not(ref c)        => { write!(f, "not ")?; operand(f, c, 2) },
+            Cond::And(ref l, ref r) => { operand(f, l, 1)?; write!(f, " and ")?; operand(f, r, 2) },
+            Cond::Or(ref l, ref r)  => { operand(f, l, 0)?; write!(f, " or ")?; operand(f, r, 1) },
         }
     }
 }
 
-fn parse(line: &str) -> Instr {
-    let mut split = line.split_whitespace();
-    let reg = split.next().unwrap().to_owned();
-    let op_type = split.next().unwrap();
-    let op_val = split.next().unwrap().parse().unwrap();
-    let op = if op_type == "inc" { Op::Inc(op_val) } else { Op::Dec(op_val) };
-    split.next(); // if 
-    let cond_reg = split.next().unwrap().to_owned();
-    let rel = to_rel_fn(split.next().unwrap());
-    let val = split.next().unwrap().parse().unwrap();
-    let cond = Cond{reg: cond_reg, rel: rel, val: val};
-    Instr{ reg, op, cond }
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.kind.name(), self.amount)
+    }
+}
+
+impl fmt::Display for Instr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} if {}", self.reg, self.op, self.cond)
+    }
+}
+
+#[derive(Debug,PartialEq)]
+enum ParseError {
+    Missing(&'static str),
+    UnknownOp(String),
+    UnknownRel(String),
+    BadOperand(String),
+    BadRegister(String),
+    ExpectedIf(String),
+    Trailing(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::Missing(what)       => write!(f, "missing {}", what),
+            ParseError::UnknownOp(ref s)    => write!(f, "unknown operation '{}'", s),
+            ParseError::UnknownRel(ref s)   => write!(f, "unknown relation '{}'", s),
+            ParseError::BadOperand(ref s)   => write!(f, "'{}' is neither a register nor a number", s),
+            ParseError::BadRegister(ref s)  => write!(f, "'{}' is not a register", s),
+            ParseError::ExpectedIf(ref s)   => write!(f, "expected 'if', found '{}'", s),
+            ParseError::Trailing(ref s)     => write!(f, "unexpected '{}' after the condition", s),
+        }
+    }
+}
+
+// Splits on whitespace, with parentheses as tokens of their own.
+fn tokenize(line: &str) -> Vec<&str> {
+    let mut tokens = vec![];
+    for mut word in line.split_whitespace() {
+        while word.starts_with('(') {
+            tokens.push("(");
+            word = &word[1..];
+        }
+        let closing = word.len() - word.trim_end_matches(')').len();
+        if closing < word.len() {
+            tokens.push(&word[..word.len() - closing]);
+        }
+        tokens.extend(std::iter::repeat_n(")", closing));
+    }
+    tokens
+}
+
+struct Tokens<'a> {
+    tokens: Vec<&'a str>,
+    pos: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn peek(&self, ahead: usize) -> Option<&'a str> {
+        self.tokens.get(self.pos + ahead).cloned()
+    }
+
+    fn next(&mut self, what: &'static str) -> Result<&'a str, ParseError> {
+        let t = self.peek(0).ok_or(ParseError::Missing(what))?;
+        self.pos += 1;
+        Ok(t)
+    }
+
+    fn operand(&mut self, what: &'static str) -> Result<Operand, ParseError> {
+        self.next(what)?.parse()
+    }
+
+    fn or(&mut self) -> Result<Cond, ParseError> {
+        let mut c = self.and()?;
+        while self.peek(0) == Some("or") {
+            self.pos += 1;
+            c = Cond::Or(Box::new(c), Box::new(self.and()?));
+        }
+        Ok(c)
+    }
+
+    fn and(&mut self) -> Result<Cond, ParseError> {
+        let mut c = self.not()?;
+        while self.peek(0) == Some("and") {
+            self.pos += 1;
+            c = Cond::And(Box::new(c), Box::new(self.not()?));
+        }
+        Ok(c)
+    }
+
+    // A register may itself be called `not`, so it is only a keyword when
+    // no relation follows it.
+    fn not(&mut self) -> Result<Cond, ParseError> {
+        let rel_follows = self.peek(1).is_some_and(|t| t.parse::<Rel>().is_ok());
+        match self.peek(0) {
+            Some("not") if !rel_follows => {
+                self.pos += 1;
+                Ok(Cond::Not(Box::new(self.not()?)))
+            },
+            Some("(") => {
+                self.pos += 1;
+                let c = self.or()?;
+                match self.next("')'")? {
+                    ")"     => Ok(c),
+                    other   => Err(ParseError::Trailing(other.to_owned())),
+                }
+            },
+            _ => {
+                let left = self.operand("condition")?;
+                let rel = self.next("relation")?.parse()?;
+                let right = self.operand("condition value")?;
+                Ok(Cond::Cmp(left, rel, right))
+            },
+        }
+    }
+}
+
+fn parse(line: &str) -> Result<Instr, ParseError> {
+    let mut tokens = Tokens{tokens: tokenize(line), pos: 0};
+    let reg = match tokens.operand("register")? {
+        Operand::Reg(r) => r,
+        Operand::Val(v) => return Err(ParseError::BadRegister(v.to_string())),
+    };
+    let kind = tokens.next("operation")?.parse()?;
+    let amount = tokens.operand("amount")?;
+    match tokens.next("'if'")? {
+        "if"    => {},
+        other   => return Err(ParseError::ExpectedIf(other.to_owned())),
+    }
+    let cond = tokens.or()?;
+    if let Some(rest) = tokens.peek(0) {
+        return Err(ParseError::Trailing(rest.to_owned()))
+    }
+    Ok(Instr{ reg, op: Op{kind, amount}, cond })
+}
+
+// Every value each register was set to, keyed by the index of the
+// instruction that set it, and whether each instruction fired.
+struct Trace {
+    history: HashMap<String, Vec<(usize, i64)>>,
+    fired: Vec<bool>,
+}
+
+impl Trace {
+    // Registers start out at 0, so that counts towards the extent too.
+    fn extent(&self, reg: &str) -> (i64, i64) {
+        self.history.get(reg).map_or((0, 0), |h| h.iter().fold((0, 0), |(lo, hi), &(_, v)| (lo.min(v), hi.max(v))))
+    }
+
+    fn skipped(&self) -> usize {
+        self.fired.iter().filter(|&&f| !f).count()
+    }
+
+    fn to_csv(&self, program: &[Instr]) -> String {
+        let mut values: HashMap<usize, i64> = HashMap::new();
+        for h in self.history.values() {
+            values.extend(h.iter().cloned());
+        }
+        let mut csv = "index,register,fired,value\n".to_owned();
+        for (i, (instr, &fired)) in program.iter().zip(&self.fired).enumerate() {
+            let value = values.get(&i).map_or(String::new(), |v| v.to_string());
+            csv += &format!("{},{},{},{}\n", i, instr.reg, fired as u8, value);
+        }
+        csv
+    }
+
+    fn peaks_csv(&self) -> String {
+        let mut names: Vec<&String> = self.history.keys().collect();
+        names.sort();
+        let mut csv = "register,min,max,changes\n".to_owned();
+        for r in names {
+            let (lo, hi) = self.extent(r);
+            csv += &format!("{},{},{},{}\n", r, lo, hi, self.history[r].len());
+        }
+        csv
+    }
 }
 
 struct Registers {
-    regs: HashMap<String, i32>,
-    top: i32,
+    regs: HashMap<String, i64>,
+    top: i64,
+    trace: Option<Trace>,
 }
 
 impl Registers {
-    fn get(&mut self, reg: &String) -> i32 {
+    fn new() -> Registers {
+        Registers{regs: HashMap::new(), top: i64::MIN, trace: None}
+    }
+
+    fn traced() -> Registers {
+        Registers{trace: Some(Trace{history: HashMap::new(), fired: vec![]}), ..Registers::new()}
+    }
+
+    fn get(&mut self, reg: &String) -> i64 {
         *self.regs.entry(reg.clone()).or_insert(0)
     }
 
-    fn set(&mut self, reg: &String, val: i32) {
+    fn set(&mut self, reg: &String, val: i64) {
         self.regs.insert(reg.clone(), val);
-        self.top = self.top.max(val)
+        self.top = self.top.max(val);
+        if let Some(ref mut t) = self.trace {
+            let index = t.fired.len();
+            t.history.entry(reg.clone()).or_insert_with(Vec::new).push((index, val));
+        }
     }
 
-    fn max(&self) -> i32 {
+    fn fired(&mut self, fired: bool) {
+        if let Some(ref mut t) = self.trace {
+            t.fired.push(fired);
+        }
+    }
+
+    fn max(&self) -> i64 {
         *self.regs.iter().max_by_key(|&(_, v)| v).unwrap().1
     }
 }
 
+// Registers interned to dense indices, conditions lowered to steps on a
+// single flag. `and`/`or` jump past their right side once the flag decides
+// them, so the right side's registers are only read when interpreting would.
+#[derive(Clone,Copy,Debug)]
+enum Arg {
+    Reg(usize),
+    Val(i64),
+}
+
+#[derive(Clone,Copy,Debug)]
+enum Step {
+    Cmp(Arg, Rel, Arg),
+    Not,
+    JumpIfFalse(usize),
+    JumpIfTrue(usize),
+}
+
+struct Code {
+    target: usize,
+    kind: OpKind,
+    amount: Arg,
+    cond: (usize, usize),
+}
+
+struct Compiled {
+    names: Vec<String>,
+    steps: Vec<Step>,
+    code: Vec<Code>,
+}
+
+fn compile(program: &[Instr]) -> Compiled {
+    fn intern(names: &mut Vec<String>, index: &mut HashMap<String, usize>, reg: &str) -> usize {
+        *index.entry(reg.to_owned()).or_insert_with(|| { names.push(reg.to_owned()); names.len() - 1 })
+    }
+    fn arg(names: &mut Vec<String>, index: &mut HashMap<String, usize>, o: &Operand) -> Arg {
+        match *o {
+            Operand::Reg(ref r) => Arg::Reg(intern(names, index, r)),
+            Operand::Val(v)     => Arg::Val(v),
+        }
+    }
+    fn lower(names: &mut Vec<String>, index: &mut HashMap<String, usize>, c: &Cond, steps: &mut Vec<Step>) {
+        match *c {
+            Cond::Cmp(ref l, rel, ref r) => {
+                let step = Step::Cmp(arg(names, index, l), rel, arg(names, index, r));
+                steps.push(step)
+            },
+            Cond::Not(ref c)        => { lower(names, index, c, steps); steps.push(Step::Not) },
+            Cond::And(ref l, ref r) => {
+                lower(names, index, l, steps);
+                let jump = steps.len();
+                steps.push(Step::JumpIfFalse(0));
+                lower(names, index, r, steps);
+                steps[jump] = Step::JumpIfFalse(steps.len());
+            },
+            Cond::Or(ref l, ref r)  => {
+                lower(names, index, l, steps);
+                let jump = steps.len();
+                steps.push(Step::JumpIfTrue(0));
+                lower(names, index, r, steps);
+                steps[jump] = Step::JumpIfTrue(steps.len());
+            },
+        }
+    }
+
+    let (mut names, mut index) = (vec![], HashMap::new());
+    let mut steps = vec![];
+    let code = program.iter().map(|instr| {
+        let start = steps.len();
+        lower(&mut names, &mut index, &instr.cond, &mut steps);
+        let amount = arg(&mut names, &mut index, &instr.op.amount);
+        let target = intern(&mut names, &mut index, &instr.reg);
+        Code{target, kind: instr.op.kind, amount, cond: (start, steps.len())}
+    }).collect();
+    Compiled{names, steps, code}
+}
+
+impl Compiled {
+    // Same registers and top as interpreting the program; `seen` tracks
+    // which registers the interpreter would have created along the way.
+    fn run(&self) -> Result<Registers, (usize, ExecError)> {
+        fn load(arg: Arg, vals: &[i64], seen: &mut [bool]) -> i64 {
+            match arg {
+                Arg::Reg(r) => { seen[r] = true; vals[r] },
+                Arg::Val(v) => v,
+            }
+        }
+
+        let mut vals = vec![0; self.names.len()];
+        let mut seen = vec![false; self.names.len()];
+        let mut top = i64::MIN;
+        for (i, c) in self.code.iter().enumerate() {
+            let (mut pc, mut fired) = (c.cond.0, false);
+            while pc < c.cond.1 {
+                match self.steps[pc] {
+                    Step::Cmp(l, rel, r) => {
+                        let l = load(l, &vals, &mut seen);
+                        fired = rel.eval(l, load(r, &vals, &mut seen));
+                    },
+                    Step::Not                       => fired = !fired,
+                    Step::JumpIfFalse(to) if !fired => { pc = to; continue },
+                    Step::JumpIfTrue(to) if fired   => { pc = to; continue },
+                    Step::JumpIfFalse(_) | Step::JumpIfTrue(_) => {},
+                }
+                pc += 1;
+            }
+            if !fired {
+                continue
+            }
+            let amount = load(c.amount, &vals, &mut seen);
+            let name = || self.names[c.target].clone();
+            if amount == 0 && (c.kind == OpKind::Div || c.kind == OpKind::Mod) {
+                return Err((i, ExecError::DivideByZero(name())))
+            }
+            seen[c.target] = true;
+            let next = c.kind.apply(vals[c.target], amount).ok_or_else(|| (i, ExecError::Overflow(name())))?;
+            vals[c.target] = next;
+            top = top.max(next);
+        }
+        let regs = self.names.iter().zip(vals).zip(seen)
+            .filter(|&(_, seen)| seen)
+            .map(|((name, val), _)| (name.clone(), val))
+            .collect();
+        Ok(Registers{regs, top, trace: None})
+    }
+}
+
+// What an instruction or `:set` overwrote, so the REPL can take it back.
+struct Undo {
+    reg: String,
+    fired: bool,
+    previous: Option<i64>,
+    peak: Option<i64>,
+    // Registers that only exist because this step read them.
+    created: Vec<String>,
+    // `:set` isn't an instruction, so there's no program line to drop.
+    command: Option<String>,
+}
+
+struct Repl {
+    registers: Registers,
+    peaks: HashMap<String, i64>,
+    program: Vec<Instr>,
+    undo: Vec<Undo>,
+}
+
+const REPL_HELP: &str = "<instr>          run one instruction, e.g. 'a inc 1 if b < 5'
+:regs            every register with its value and historical maximum
+:get <reg>       one register
+:set <reg> <n>   overwrite a register
+:top             highest value ever held
+:undo            take back the last instruction or :set
+:load <file>     run every instruction in a file
+:save <file>     write the instructions run so far
+:quit";
+
+impl Repl {
+    fn new() -> Repl {
+        Repl{
+            registers: Registers::new(),
+            peaks: HashMap::new(),
+            program: vec![],
+            undo: vec![],
+        }
+    }
+
+    fn run(&mut self, instr: Instr) -> Result<bool, String> {
+        let mut reads = vec![&instr.reg];
+        reads.extend(instr.op.amount.register());
+        instr.cond.registers(&mut reads);
+        reads.sort();
+        reads.dedup();
+        let missing: Vec<String> = reads.into_iter()
+            .filter(|r| !self.registers.regs.contains_key(*r))
+            .cloned()
+            .collect();
+        let previous = self.registers.regs.get(&instr.reg).cloned();
+        let peak = self.peaks.get(&instr.reg).cloned();
+        let fired = match instr.exec(&mut self.registers) {
+            Ok(fired)   => fired,
+            Err(e)      => {
+                for r in &missing {
+                    self.registers.regs.remove(r);
+                }
+                return Err(e.to_string())
+            },
+        };
+        if fired {
+            self.record(&instr.reg);
+        }
+        let created = missing.into_iter().filter(|r| self.registers.regs.contains_key(r)).collect();
+        self.undo.push(Undo{reg: instr.reg.clone(), fired, previous, peak, created, command: None});
+        self.program.push(instr);
+        Ok(fired)
+    }
+
+    fn record(&mut self, reg: &String) {
+        let val = self.registers.get(reg);
+        let peak = self.peaks.entry(reg.clone()).or_insert(val);
+        *peak = (*peak).max(val);
+    }
+
+    fn describe(&self, reg: &String) -> String {
+        let val = self.registers.regs.get(reg).cloned().unwrap_or(0);
+        match self.peaks.get(reg) {
+            Some(peak)  => format!("{} = {} (max {})", reg, val, peak),
+            None        => format!("{} = {}", reg, val),
+        }
+    }
+
+    fn command(&mut self, line: &str) -> Result<String, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => Ok(String::new()),
+            [":help"] => Ok(REPL_HELP.to_owned()),
+            [":regs"] => {
+                let mut names: Vec<String> = self.registers.regs.keys().cloned().collect();
+                names.sort();
+                Ok(names.iter().map(|r| self.describe(r)).collect::<Vec<_>>().join("\n"))
+            },
+            [":get", reg] => Ok(self.describe(&reg.to_string())),
+            [":set", reg, val] => {
+                let reg = match reg.parse().map_err(|e: ParseError| e.to_string())? {
+                    Operand::Reg(r) => r,
+                    Operand::Val(v) => return Err(ParseError::BadRegister(v.to_string()).to_string()),
+                };
+                let val: i64 = val.parse().map_err(|_| format!("'{}' is not a number", val))?;
+                let previous = self.registers.regs.get(&reg).cloned();
+                self.undo.push(Undo{
+                    reg: reg.clone(),
+                    fired: true,
+                    previous,
+                    peak: self.peaks.get(&reg).cloned(),
+                    created: if previous.is_none() { vec![reg.clone()] } else { vec![] },
+                    command: Some(format!(":set {} {}", reg, val)),
+                });
+                self.registers.set(&reg, val);
+                self.record(&reg);
+                Ok(self.describe(&reg))
+            },
+            [":top"] if self.peaks.is_empty() => Ok("no register set yet".to_owned()),
+            [":top"] => Ok(self.registers.top.to_string()),
+            [":undo"] => {
+                let undo = self.undo.pop().ok_or("nothing to undo")?;
+                let what = match undo.command {
+                    Some(command)   => command,
+                    None            => self.program.pop().unwrap().to_string(),
+                };
+                // A skipped instruction changed nothing beyond what it read.
+                if undo.fired {
+                    if let Some(v) = undo.previous {
+                        self.registers.regs.insert(undo.reg.clone(), v);
+                    }
+                    match undo.peak {
+                        Some(p) => { self.peaks.insert(undo.reg.clone(), p); },
+                        None    => { self.peaks.remove(&undo.reg); },
+                    }
+                }
+                for r in &undo.created {
+                    self.registers.regs.remove(r);
+                }
+                // Every value ever held was recorded as some register's peak.
+                self.registers.top = self.peaks.values().cloned().max().unwrap_or(i64::MIN);
+                Ok(format!("undid '{}'", what))
+            },
+            [":load", file] => {
+                let f = std::fs::File::open(file).map_err(|e| format!("{}: {}", file, e))?;
+                let (mut run, mut fired) = (0, 0);
+                for (i, l) in BufReader::new(f).lines().enumerate() {
+                    let l = l.map_err(|e| format!("{}: {}", file, e))?;
+                    let instr = parse(&l).map_err(|e| format!("{} line {}: {}", file, i + 1, e))?;
+                    if self.run(instr).map_err(|e| format!("{} line {}: {}", file, i + 1, e))? {
+                        fired += 1;
+                    }
+                    run += 1;
+                }
+                Ok(format!("ran {} instructions, {} fired", run, fired))
+            },
+            [":save", file] => {
+                let text: String = self.program.iter().map(|i| format!("{}\n", i)).collect();
+                std::fs::write(file, text).map_err(|e| format!("{}: {}", file, e))?;
+                Ok(format!("saved {} instructions", self.program.len()))
+            },
+            _ if words[0].starts_with(':') => Err(format!("unknown command '{}', try :help", line.trim())),
+            _ => {
+                let instr = parse(line).map_err(|e| e.to_string())?;
+                let reg = instr.reg.clone();
+                match self.run(instr)? {
+                    true    => Ok(self.describe(&reg)),
+                    false   => Ok("skipped".to_owned()),
+                }
+            },
+        }
+    }
+}
+
+fn repl() {
+    let mut repl = Repl::new();
+    let stdin = stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("> ");
+        std::io::stdout().flush().unwrap();
+        let line = match lines.next() {
+            Some(l) => l.unwrap(),
+            None    => break,
+        };
+        if line.trim() == ":quit" {
+            break
+        }
+        match repl.command(&line) {
+            Ok(ref out) if out.is_empty() => {},
+            Ok(out)     => println!("{}", out),
+            Err(e)      => println!("error: {}", e),
+        }
+    }
+}
+
 fn main() {
-    let mut registers = Registers{regs: HashMap::new(), top: i32::min_value()};
-    BufReader::new(stdin())
-        .lines()
-        .map(|l| parse(&l.unwrap()))
-        .for_each(|instr| instr.exec(&mut registers));
-    println!("{}", registers.max());
-    println!("{}", registers.top);
+    if std::env::args().nth(1).is_some_and(|a| a == "repl") {
+        return repl()
+    }
+    let mode = std::env::args().nth(1);
+    let mut program = vec![];
+    for (i, l) in BufReader::new(stdin()).lines().enumerate() {
+        match parse(&l.unwrap()) {
+            Ok(instr)   => program.push(instr),
+            Err(e)      => { eprintln!("line {}: {}", i + 1, e); std::process::exit(1) },
+        }
+    }
+    let fail = |i: usize, e: ExecError| -> ! { eprintln!("line {}: {}", i + 1, e); std::process::exit(1) };
+    if mode.as_ref().is_some_and(|m| m == "fast") {
+        let registers = compile(&program).run().unwrap_or_else(|(i, e)| fail(i, e));
+        println!("{}", registers.max());
+        println!("{}", registers.top);
+        return
+    }
+    let mut registers = if mode.is_some() { Registers::traced() } else { Registers::new() };
+    for (i, instr) in program.iter().enumerate() {
+        if let Err(e) = instr.exec(&mut registers) {
+            fail(i, e)
+        }
+    }
+    match (mode.as_deref(), &registers.trace) {
+        (Some("trace"), Some(t))    => {
+            print!("{}", t.to_csv(&program));
+            eprintln!("{} fired, {} skipped", t.fired.len() - t.skipped(), t.skipped());
+        },
+        (Some("peaks"), Some(t))    => print!("{}", t.peaks_csv()),
+        _                           => {
+            println!("{}", registers.max());
+            println!("{}", registers.top);
+        },
+    }
+}
+
+#[cfg(test)]
+const EXAMPLE: &str = "b inc 5 if a > 1
+a inc 1 if b < 5
+c dec -10 if a >= 1
+c inc -20 if c == 10";
+
+#[test]
+fn exec_test() {
+    let mut registers = Registers::new();
+    for l in EXAMPLE.lines() {
+        parse(l).unwrap().exec(&mut registers).unwrap();
+    }
+    assert_eq!(1, registers.max());
+    assert_eq!(10, registers.top);
+}
+
+#[test]
+fn display_round_trip_test() {
+    for l in EXAMPLE.lines().chain(vec!["x dec 3 if y != z", "q inc 0 if -1 <= q"]) {
+        let instr = parse(l).unwrap();
+        assert_eq!(l, instr.to_string());
+        assert_eq!(instr, parse(&instr.to_string()).unwrap());
+    }
+}
+
+#[test]
+fn register_condition_test() {
+    let mut registers = Registers::new();
+    assert_eq!(Ok(true), parse("a inc 3 if a == b").unwrap().exec(&mut registers));
+    assert_eq!(Ok(true), parse("b inc 1 if a > b").unwrap().exec(&mut registers));
+    assert_eq!(Ok(false), parse("c inc 1 if a < b").unwrap().exec(&mut registers));
+    assert_eq!((3, 1, 0), (registers.get(&"a".to_owned()), registers.get(&"b".to_owned()), registers.get(&"c".to_owned())));
+}
+
+#[test]
+fn parse_error_test() {
+    assert_eq!(Err(ParseError::UnknownRel("=>".to_owned())), parse("a inc 1 if b => 1"));
+    assert_eq!(Err(ParseError::UnknownOp("add".to_owned())), parse("a add 1 if b > 1"));
+    assert_eq!(Err(ParseError::ExpectedIf("when".to_owned())), parse("a inc 1 when b > 1"));
+    assert_eq!(Err(ParseError::Missing("condition value")), parse("a inc 1 if b >"));
+    assert_eq!(Err(ParseError::BadOperand("B".to_owned())), parse("a inc 1 if B > 1"));
+    assert_eq!(Err(ParseError::BadRegister("1".to_owned())), parse("1 inc 1 if b > 1"));
+    assert_eq!(Err(ParseError::Trailing("x".to_owned())), parse("a inc 1 if b > 1 x"));
+    assert_eq!("unknown relation '=>'", parse("a inc 1 if b => 1").unwrap_err().to_string());
+}
+
+#[cfg(test)]
+fn run(program: &str) -> Registers {
+    let mut registers = Registers::new();
+    for l in program.lines() {
+        parse(l).unwrap().exec(&mut registers).unwrap();
+    }
+    registers
+}
+
+#[test]
+fn extended_ops_test() {
+    let mut r = run("a set 7 if a == 0
+b set a if 1 > 0
+b mul 3 if b == a
+b div 2 if b > 20
+c set b if 0 == 0
+c mod 4 if c != 0
+d inc b if b > a
+d dec c if d > 0
+e set 5000000000 if e == 0
+e mul 3 if e > 0");
+    let mut get = |n: &str| r.get(&n.to_owned());
+    assert_eq!((7, 10, 2, 8), (get("a"), get("b"), get("c"), get("d")));
+    assert_eq!(15_000_000_000, get("e"));
+}
+
+#[test]
+fn compound_condition_test() {
+    let mut r = run("a set 1 if x == 0
+b inc 1 if a == 1 and x == 0
+c inc 1 if a == 2 or x == 0
+d inc 1 if not a == 1
+e inc 1 if not (a == 1 and b == 0) or c > 5
+f inc 1 if a == 1 and (b == 0 or c == 1)");
+    let mut get = |n: &str| r.get(&n.to_owned());
+    assert_eq!((1, 1, 0, 1, 1), (get("b"), get("c"), get("d"), get("e"), get("f")));
+}
+
+#[test]
+fn compound_display_test() {
+    for l in [
+        "a mul b if x == 1 and y == 2 or not z > 3",
+        "a mod 3 if (x == 1 or y == 2) and z > 3",
+        "a set -4 if not (x == 1 and y == 2)",
+        "a div 2 if not not x < y",
+        "not inc 1 if not > 0 and not not == 1",
+    ] {
+        let instr = parse(l).unwrap();
+        assert_eq!(l, instr.to_string());
+    }
+    let instr = parse("a inc 1 if ((x == 1)) and (y == 2)").unwrap();
+    assert_eq!("a inc 1 if x == 1 and y == 2", instr.to_string());
+}
+
+#[test]
+fn exec_error_test() {
+    let mut registers = Registers::new();
+    assert_eq!(Err(ExecError::DivideByZero("a".to_owned())), parse("a div b if 0 == 0").unwrap().exec(&mut registers));
+    assert_eq!(Ok(true), parse("a set 9223372036854775807 if 0 == 0").unwrap().exec(&mut registers));
+    assert_eq!(Err(ExecError::Overflow("a".to_owned())), parse("a inc 1 if 0 == 0").unwrap().exec(&mut registers));
+    assert_eq!(Err(ParseError::Missing("')'")), parse("a inc 1 if (x == 1"));
+    assert_eq!(Err(ParseError::Trailing(")".to_owned())), parse("a inc 1 if x == 1)"));
+}
+
+#[test]
+fn repl_test() {
+    let mut repl = Repl::new();
+    assert_eq!(Ok("no register set yet".to_owned()), repl.command(":top"));
+    let outputs: Vec<_> = EXAMPLE.lines().map(|l| repl.command(l)).collect();
+    assert_eq!(vec![
+        Ok("skipped".to_owned()),
+        Ok("a = 1 (max 1)".to_owned()),
+        Ok("c = 10 (max 10)".to_owned()),
+        Ok("c = -10 (max 10)".to_owned()),
+    ], outputs);
+    assert_eq!(Ok("a = 1 (max 1)\nb = 0\nc = -10 (max 10)".to_owned()), repl.command(":regs"));
+    assert_eq!(Ok("10".to_owned()), repl.command(":top"));
+    assert_eq!(Ok("undid 'c inc -20 if c == 10'".to_owned()), repl.command(":undo"));
+    assert_eq!(Ok("c = 10 (max 10)".to_owned()), repl.command(":get c"));
+    assert_eq!(Ok("undid 'c dec -10 if a >= 1'".to_owned()), repl.command(":undo"));
+    assert_eq!(Ok("c = 0".to_owned()), repl.command(":get c"));
+    assert_eq!(Ok("1".to_owned()), repl.command(":top"));
+    assert_eq!(Ok("b = 7 (max 7)".to_owned()), repl.command(":set b 7"));
+    assert_eq!(Ok("7".to_owned()), repl.command(":top"));
+    assert!(repl.command(":set 1 b").is_err());
+    assert!(repl.command("a inc").is_err());
+    assert!(repl.command(":bogus").is_err());
+    assert_eq!(Ok("undid ':set b 7'".to_owned()), repl.command(":undo"));
+    assert_eq!(Ok("b = 0".to_owned()), repl.command(":get b"));
+    assert_eq!(Ok("1".to_owned()), repl.command(":top"));
+    repl.command(":undo").unwrap();
+    repl.command(":undo").unwrap();
+    assert_eq!(Err("nothing to undo".to_owned()), repl.command(":undo"));
+    assert_eq!(Ok(String::new()), repl.command(":regs"));
+}
+
+#[test]
+fn repl_undo_set_test() {
+    let mut repl = Repl::new();
+    assert_eq!(Ok("skipped".to_owned()), repl.command("b inc 5 if a > 1"));
+    assert_eq!(Ok("b = 7 (max 7)".to_owned()), repl.command(":set b 7"));
+    assert_eq!(Ok("undid ':set b 7'".to_owned()), repl.command(":undo"));
+    assert_eq!(Ok("a = 0".to_owned()), repl.command(":regs"));
+    assert_eq!(Ok("no register set yet".to_owned()), repl.command(":top"));
+    assert_eq!(Ok("undid 'b inc 5 if a > 1'".to_owned()), repl.command(":undo"));
+    assert_eq!(Ok(String::new()), repl.command(":regs"));
+
+    repl.command(":set a 3").unwrap();
+    assert_eq!(Ok("a = 5 (max 5)".to_owned()), repl.command("a inc 2 if a > 1"));
+    assert_eq!(Ok("a = 1 (max 5)".to_owned()), repl.command(":set a 1"));
+    assert_eq!(Ok("skipped".to_owned()), repl.command("a inc 1 if c > 1"));
+    assert_eq!(Ok("a = 1 (max 5)\nc = 0".to_owned()), repl.command(":regs"));
+    repl.command(":undo").unwrap();
+    assert_eq!(Ok("a = 1 (max 5)".to_owned()), repl.command(":regs"));
+    repl.command(":undo").unwrap();
+    assert_eq!(Ok("a = 5 (max 5)".to_owned()), repl.command(":get a"));
+    repl.command(":undo").unwrap();
+    assert_eq!(Ok("a = 3 (max 3)".to_owned()), repl.command(":get a"));
+    assert_eq!(Ok("3".to_owned()), repl.command(":top"));
+    assert!(repl.command("a div z if 0 == 0").is_err());
+    assert_eq!(Ok("a = 3 (max 3)".to_owned()), repl.command(":regs"));
+}
+
+#[test]
+fn repl_load_save_test() {
+    let path = std::env::temp_dir().join(format!("day08-repl-{}.txt", std::process::id()));
+    let path = path.to_str().unwrap();
+    std::fs::write(path, EXAMPLE).unwrap();
+    let mut repl = Repl::new();
+    assert_eq!(Ok("ran 4 instructions, 3 fired".to_owned()), repl.command(&format!(":load {}", path)));
+    repl.command("d set c if c < 0").unwrap();
+    assert_eq!(Ok("saved 5 instructions".to_owned()), repl.command(&format!(":save {}", path)));
+    let saved = std::fs::read_to_string(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(format!("{}\nd set c if c < 0\n", EXAMPLE), saved);
+}
+
+#[test]
+fn trace_test() {
+    let mut registers = Registers::traced();
+    let program: Vec<Instr> = EXAMPLE.lines().chain(vec!["a dec 4 if c < 0", "a inc 9 if a < 0"]).map(|l| parse(l).unwrap()).collect();
+    for instr in &program {
+        instr.exec(&mut registers).unwrap();
+    }
+    let trace = registers.trace.unwrap();
+    assert_eq!(vec![false, true, true, true, true, true], trace.fired);
+    assert_eq!(Some(&vec![(1, 1), (4, -3), (5, 6)]), trace.history.get("a"));
+    assert_eq!(Some(&vec![(2, 10), (3, -10)]), trace.history.get("c"));
+    assert_eq!(None, trace.history.get("b"));
+    assert_eq!((-3, 6), trace.extent("a"));
+    assert_eq!((-10, 10), trace.extent("c"));
+    assert_eq!((0, 0), trace.extent("b"));
+    assert_eq!(1, trace.skipped());
+    assert_eq!("index,register,fired,value
+0,b,0,
+1,a,1,1
+2,c,1,10
+3,c,1,-10
+4,a,1,-3
+5,a,1,6
+", trace.to_csv(&program));
+    assert_eq!("register,min,max,changes
+a,-3,6,3
+c,-10,10,2
+", trace.peaks_csv());
+}
+
+#[cfg(test)]
+fn interpret(program: &[Instr]) -> Result<Registers, (usize, ExecError)> {
+    let mut registers = Registers::new();
+    for (i, instr) in program.iter().enumerate() {
+        instr.exec(&mut registers).map_err(|e| (i, e))?;
+    }
+    Ok(registers)
+}
+
+#[cfg(test)]
+fn same_outcome(program: &[Instr]) {
+    match (interpret(program), compile(program).run()) {
+        (Ok(a), Ok(b))      => assert_eq!((a.regs, a.top), (b.regs, b.top)),
+        (Err(a), Err(b))    => assert_eq!(a, b),
+        (a, b)              => panic!("interpreter {:?}, compiled {:?}", a.map(|r| r.regs), b.map(|r| r.regs)),
+    }
+}
+
+#[test]
+fn compiled_example_test() {
+    let program: Vec<Instr> = EXAMPLE.lines().map(|l| parse(l).unwrap()).collect();
+    let registers = compile(&program).run().unwrap();
+    assert_eq!((1, 10), (registers.max(), registers.top));
+    same_outcome(&program);
+    let program: Vec<Instr> = ["a set 1 if 0 == 0", "x set 5 if a > 2 or y < 0", "b div c if a == 1"].iter()
+        .map(|l| parse(l).unwrap())
+        .collect();
+    assert_eq!(Err((2, ExecError::DivideByZero("b".to_owned()))), compile(&program).run().map(|r| r.top));
+    same_outcome(&program[..2]);
+    same_outcome(&program);
+    let program: Vec<Instr> = ["a dec 5 if a == 0", "b dec 1 if a < 0 or z == 0", "c inc 1 if a > 0 and y == 0"].iter()
+        .map(|l| parse(l).unwrap())
+        .collect();
+    let registers = compile(&program).run().unwrap();
+    assert_eq!(-1, registers.max());
+    let mut names: Vec<&String> = registers.regs.keys().collect();
+    names.sort();
+    assert_eq!(vec!["a", "b"], names);
+    same_outcome(&program);
+}
+
+// Every comparison between two of a few operands, alone and joined with one more.
+#[cfg(test)]
+fn conditions() -> Vec<String> {
+    let operands = ["a", "b", "1"];
+    let mut atoms = vec![];
+    for x in &operands {
+        for rel in &["<", "<=", ">", ">=", "==", "!="] {
+            for y in &operands {
+                atoms.push(format!("{} {} {}", x, rel, y));
+            }
+        }
+    }
+    let mut conds = atoms.clone();
+    for x in &atoms {
+        for joiner in &["and", "or", "and not", "or not"] {
+            for y in &atoms {
+                conds.push(format!("{} {} {}", x, joiner, y));
+            }
+        }
+    }
+    conds
+}
+
+// Every instruction over two registers, including ones that divide by zero.
+#[cfg(test)]
+fn instructions() -> Vec<String> {
+    let mut lines = vec![];
+    for reg in &["a", "b"] {
+        for op in &["inc", "dec", "mul", "div", "mod", "set"] {
+            for amount in &["a", "b", "0", "2"] {
+                for cond in &["a < b", "a == 0", "b != 1 or not a > 0"] {
+                    lines.push(format!("{} {} {} if {}", reg, op, amount, cond));
+                }
+            }
+        }
+    }
+    lines
+}
+
+#[test]
+fn compiled_conditions_test() {
+    let setups: [&[&str]; 3] = [&[], &["a inc 1 if 0 == 0"], &["b dec 1 if 0 == 0", "a set 3 if b < 0"]];
+    for cond in conditions() {
+        for setup in &setups {
+            let program: Vec<Instr> = setup.iter()
+                .map(|l| l.to_string())
+                .chain(Some(format!("b inc 1 if {}", cond)))
+                .map(|l| parse(&l).unwrap())
+                .collect();
+            same_outcome(&program);
+        }
+    }
+}
+
+#[test]
+fn compiled_instructions_test() {
+    let instrs: Vec<Instr> = instructions().iter().map(|l| parse(l).unwrap()).collect();
+    for x in &instrs {
+        same_outcome(std::slice::from_ref(x));
+        for y in &instrs {
+            same_outcome(&[x.clone(), y.clone()]);
+        }
+    }
 }
@@ -1,37 +1,424 @@
-use std::io::{Cursor,Read};
+use std::io::{BufReader,Read};
+use std::sync::{Arc,Mutex,mpsc};
+use std::thread;
+#[cfg(test)]
+use std::io::Cursor;
 
 #[derive(PartialEq,Debug,Default)]
-struct Result{groups: i32, score: i32, garbage: i32}
+struct Result{groups: i64, score: i64, garbage: i64}
 
+// What a byte means, given the bytes before it. Depth is left to the
+// caller, so the same scanner serves counting, checking and parsing.
+#[derive(Clone,Copy,PartialEq,Debug)]
+enum Token {
+    Open,
+    Close,
+    GarbageStart,
+    GarbageEnd,
+    Garbage(u8),
+    // A '>' outside garbage.
+    StrayGarbageEnd,
+    // A '!', in or out of garbage, and the byte it cancels.
+    Cancel,
+    Cancelled,
+    Other,
+}
+
+#[derive(Clone,Copy,PartialEq,Debug,Default)]
+struct Scanner {
+    garbage: bool,
+    cancel: bool,
+}
+
+impl Scanner {
+    fn scan(&mut self, c: u8) -> Token {
+        if self.cancel {
+            self.cancel = false;
+            return Token::Cancelled;
+        }
+        match (self.garbage, c) {
+            (_, b'!')       => { self.cancel = true; Token::Cancel },
+            (true, b'>')    => { self.garbage = false; Token::GarbageEnd },
+            (true, c)       => Token::Garbage(c),
+            (false, b'{')   => Token::Open,
+            (false, b'}')   => Token::Close,
+            (false, b'<')   => { self.garbage = true; Token::GarbageStart },
+            (false, b'>')   => Token::StrayGarbageEnd,
+            (false, _)      => Token::Other,
+        }
+    }
+}
+
+// A group scores when it closes. A '>' outside garbage is skipped, as
+// check_groups skips it while recovering, rather than opening garbage.
 fn count_groups<R: Read>(r: R) -> Result {
-    let mut result : Result = Default::default();
-
-    let mut depth = 0;
-    let mut in_garbage = false;
-    let mut last = None;
-
-    for c in r.bytes().map(|b| b.unwrap() as char) {
-        if last == Some('!') {
-            last = None;
-            if in_garbage { result.garbage -= 1; }
-            continue;
-        } else if c != '>' && in_garbage {
-            last = Some(c);
-            result.garbage += 1;
-            continue;
-        }
-        match c {
-            '{'         => { result.groups +=1; depth +=1; },
-            '}'         => { result.score += depth; depth -= 1; },
-            '<' | '>'   => { in_garbage = !in_garbage; },
-            _           => {},
-        }
-        last = Some(c);
+    let mut sum = Summary::default();
+    let mut scanner = Scanner::default();
+
+    for c in r.bytes().map(|b| b.unwrap()) {
+        step(&mut scanner, &mut sum, c);
+    }
+    Result{groups: sum.groups, score: sum.score, garbage: sum.garbage}
+}
+
+// What one chunk adds to the totals, with depth and score relative to the
+// depth the chunk starts at, so chunks can be counted independently.
+#[derive(Clone,Copy,Default,Debug)]
+struct Summary {
+    groups: i64,
+    score: i64,
+    garbage: i64,
+    closes: i64,
+    depth: i64,
+}
+
+impl Summary {
+    fn then(&self, next: &Summary) -> Summary {
+        Summary{
+            groups: self.groups + next.groups,
+            score: self.score + next.score + self.depth * next.closes,
+            garbage: self.garbage + next.garbage,
+            closes: self.closes + next.closes,
+            depth: self.depth + next.depth,
+        }
+    }
+}
+
+// One byte of count_groups.
+fn step(scanner: &mut Scanner, sum: &mut Summary, c: u8) {
+    match scanner.scan(c) {
+        Token::Open         => { sum.groups += 1; sum.depth += 1; },
+        Token::Close        => { sum.score += sum.depth; sum.closes += 1; sum.depth -= 1; },
+        Token::Garbage(_)   => sum.garbage += 1,
+        _                   => {},
+    }
+}
+
+// Whether a chunk starts in garbage can only be known once every chunk
+// before it is counted, so count it both ways. The two runs agree as soon
+// as they reach the same state, which tends to happen within a few bytes,
+// and from there on one run does for both.
+fn summarize(chunk: &[u8], cancel: bool) -> [(Summary, Scanner); 2] {
+    let mut runs = [
+        (Summary::default(), Scanner{garbage: false, cancel}),
+        (Summary::default(), Scanner{garbage: true, cancel}),
+    ];
+    for (i, &c) in chunk.iter().enumerate() {
+        if runs[0].1 == runs[1].1 {
+            let (mut tail, mut state) = (Summary::default(), runs[0].1);
+            for &c in &chunk[i..] {
+                step(&mut state, &mut tail, c);
+            }
+            for run in runs.iter_mut() {
+                *run = (run.0.then(&tail), state);
+            }
+            return runs;
+        }
+        for run in runs.iter_mut() {
+            step(&mut run.1, &mut run.0, c);
+        }
+    }
+    runs
+}
+
+// A '!' cancels the next byte in and out of garbage alike, so whether the
+// byte after a chunk is cancelled depends only on the run of '!'s ending it.
+fn ends_cancelling(chunk: &[u8], cancel: bool) -> bool {
+    let run = chunk.iter().rev().take_while(|&&c| c == b'!').count();
+    if run == chunk.len() { cancel ^ (run % 2 == 1) } else { run % 2 == 1 }
+}
+
+// The same counts as count_groups, reading `chunk` bytes at a time and
+// counting them on `threads` threads.
+fn count_groups_parallel<R: Read>(mut r: R, chunk: usize, threads: usize) -> Result {
+    assert!(chunk > 0, "chunks must hold at least one byte");
+    assert!(threads > 0, "at least one thread must count");
+    let (work_tx, work_rx) = mpsc::sync_channel::<(usize, Vec<u8>, bool)>(threads * 2);
+    let (done_tx, done_rx) = mpsc::channel();
+    let work_rx = Arc::new(Mutex::new(work_rx));
+    let workers: Vec<_> = (0..threads).map(|_| {
+        let (work_rx, done_tx) = (work_rx.clone(), done_tx.clone());
+        thread::spawn(move || loop {
+            let next = work_rx.lock().unwrap().recv();
+            match next {
+                Ok((index, bytes, cancel))  => done_tx.send((index, summarize(&bytes, cancel))).unwrap(),
+                Err(_)                      => break,
+            }
+        })
+    }).collect();
+    drop(done_tx);
+
+    let mut cancel = false;
+    let mut chunks = 0;
+    loop {
+        let mut bytes = Vec::with_capacity(chunk);
+        r.by_ref().take(chunk as u64).read_to_end(&mut bytes).unwrap();
+        if bytes.is_empty() {
+            break;
+        }
+        let next = ends_cancelling(&bytes, cancel);
+        work_tx.send((chunks, bytes, cancel)).unwrap();
+        cancel = next;
+        chunks += 1;
+    }
+    drop(work_tx);
+
+    let mut summaries: Vec<_> = done_rx.iter().collect();
+    for w in workers {
+        w.join().unwrap();
+    }
+    summaries.sort_by_key(|&(index, _)| index);
+    let (mut total, mut garbage) = (Summary::default(), false);
+    for (_, runs) in summaries {
+        let (sum, state) = runs[garbage as usize];
+        total = total.then(&sum);
+        garbage = state.garbage;
+    }
+    Result{groups: total.groups, score: total.score, garbage: total.garbage}
+}
+
+// Offsets are of the offending byte, or of where the unclosed group or
+// garbage started.
+#[derive(PartialEq,Debug)]
+enum StreamError {
+    UnmatchedClose(usize),
+    StrayGarbageEnd(usize),
+    UnclosedGroup(usize),
+    UnterminatedGarbage(usize),
+    TrailingCancel(usize),
+}
+
+impl std::fmt::Display for StreamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            StreamError::UnmatchedClose(p)      => write!(f, "byte {}: '}}' without an open group", p),
+            StreamError::StrayGarbageEnd(p)     => write!(f, "byte {}: '>' outside garbage", p),
+            StreamError::UnclosedGroup(p)       => write!(f, "byte {}: group is never closed", p),
+            StreamError::UnterminatedGarbage(p) => write!(f, "byte {}: garbage is never terminated", p),
+            StreamError::TrailingCancel(p)      => write!(f, "byte {}: '!' at the end of the stream", p),
+        }
+    }
+}
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+enum Mode {
+    // Skip over each error and keep counting.
+    Recover,
+    // Give up at the first error.
+    Strict,
+}
+
+// Counts like count_groups, so while recovering the totals are those of the
+// stream with its stray '}'s taken out, and unclosed groups don't score.
+fn check_groups<R: Read>(r: R, mode: Mode) -> std::result::Result<(Result, Vec<StreamError>), StreamError> {
+    let mut result: Result = Default::default();
+    let mut errors = vec![];
+    let mut open = vec![];
+    let (mut garbage, mut cancel) = (0, 0);
+    let mut scanner = Scanner::default();
+
+    {
+        let mut report = |e| match mode {
+            Mode::Strict    => Err(e),
+            Mode::Recover   => { errors.push(e); Ok(()) },
+        };
+        for (pos, b) in BufReader::new(r).bytes().map(|b| b.unwrap()).enumerate() {
+            match scanner.scan(b) {
+                Token::Open             => { open.push(pos); result.groups += 1; },
+                Token::Close            => match open.pop() {
+                    Some(_) => result.score += open.len() as i64 + 1,
+                    None    => report(StreamError::UnmatchedClose(pos))?,
+                },
+                Token::GarbageStart     => garbage = pos,
+                Token::Garbage(_)       => result.garbage += 1,
+                Token::StrayGarbageEnd  => report(StreamError::StrayGarbageEnd(pos))?,
+                Token::Cancel           => cancel = pos,
+                _                       => {},
+            }
+        }
+        for &start in &open {
+            report(StreamError::UnclosedGroup(start))?;
+        }
+        if scanner.garbage {
+            report(StreamError::UnterminatedGarbage(garbage))?;
+        }
+        if scanner.cancel {
+            report(StreamError::TrailingCancel(cancel))?;
+        }
+    }
+    Ok((result, errors))
+}
+
+// Spans are byte offsets, end exclusive. Garbage sits at the depth of the
+// group around it and keeps its contents with cancellations removed.
+#[derive(PartialEq,Debug)]
+enum Kind {
+    Group(Vec<Node>),
+    Garbage(String),
+}
+
+#[derive(PartialEq,Debug)]
+struct Node {
+    start: usize,
+    end: usize,
+    depth: i32,
+    kind: Kind,
+}
+
+impl Node {
+    fn score(&self) -> i32 {
+        match self.kind {
+            Kind::Group(_)      => self.depth,
+            Kind::Garbage(_)    => 0,
+        }
+    }
+
+    fn children(&self) -> &[Node] {
+        match self.kind {
+            Kind::Group(ref c)  => c,
+            Kind::Garbage(_)    => &[],
+        }
+    }
+}
+
+// Unclosed groups and garbage are closed off at the end of the stream and
+// stray '}'s are dropped.
+fn parse_tree<R: Read>(r: R) -> Vec<Node> {
+    let mut roots = vec![];
+    let mut open: Vec<(usize, Vec<Node>)> = vec![];
+    let mut garbage: Option<(usize, Vec<u8>)> = None;
+    let mut scanner = Scanner::default();
+    let mut len = 0;
+
+    fn attach(open: &mut [(usize, Vec<Node>)], roots: &mut Vec<Node>, node: Node) {
+        match open.last_mut() {
+            Some(&mut (_, ref mut children))    => children.push(node),
+            None                                => roots.push(node),
+        }
+    }
+
+    for (pos, b) in BufReader::new(r).bytes().map(|b| b.unwrap()).enumerate() {
+        len = pos + 1;
+        let depth = open.len() as i32;
+        match scanner.scan(b) {
+            Token::Open         => open.push((pos, vec![])),
+            Token::Close        => if let Some((start, children)) = open.pop() {
+                attach(&mut open, &mut roots, Node{start, end: pos + 1, depth, kind: Kind::Group(children)});
+            },
+            Token::GarbageStart => garbage = Some((pos, vec![])),
+            Token::Garbage(b)   => if let Some((_, ref mut content)) = garbage {
+                content.push(b);
+            },
+            Token::GarbageEnd   => if let Some((start, content)) = garbage.take() {
+                let kind = Kind::Garbage(String::from_utf8_lossy(&content).into_owned());
+                attach(&mut open, &mut roots, Node{start, end: pos + 1, depth, kind});
+            },
+            _                   => {},
+        }
+    }
+    if let Some((start, content)) = garbage {
+        let (depth, kind) = (open.len() as i32, Kind::Garbage(String::from_utf8_lossy(&content).into_owned()));
+        attach(&mut open, &mut roots, Node{start, end: len, depth, kind});
+    }
+    while let Some((start, children)) = open.pop() {
+        let depth = open.len() as i32 + 1;
+        attach(&mut open, &mut roots, Node{start, end: len, depth, kind: Kind::Group(children)});
+    }
+    roots
+}
+
+fn preorder<'a>(nodes: &'a [Node], out: &mut Vec<&'a Node>) {
+    for n in nodes {
+        out.push(n);
+        preorder(n.children(), out);
+    }
+}
+
+fn nodes(roots: &[Node]) -> Vec<&Node> {
+    let mut out = vec![];
+    preorder(roots, &mut out);
+    out
+}
+
+// The first group in stream order with the highest score.
+fn highest_score(roots: &[Node]) -> Option<&Node> {
+    nodes(roots).into_iter()
+        .filter(|n| n.score() > 0)
+        .fold(None, |best: Option<&Node>, n| match best {
+            Some(b) if b.score() >= n.score()   => Some(b),
+            _                                   => Some(n),
+        })
+}
+
+fn garbage_contents(roots: &[Node]) -> Vec<&str> {
+    nodes(roots).into_iter().filter_map(|n| match n.kind {
+        Kind::Garbage(ref s)    => Some(s.as_str()),
+        Kind::Group(_)          => None,
+    }).collect()
+}
+
+fn totals(roots: &[Node]) -> Result {
+    let mut result: Result = Default::default();
+    for n in nodes(roots) {
+        match n.kind {
+            Kind::Group(_)          => { result.groups += 1; result.score += i64::from(n.score()); },
+            Kind::Garbage(ref s)    => result.garbage += s.len() as i64,
+        }
     }
     result
 }
 
+fn pretty(roots: &[Node]) -> String {
+    let mut out = String::new();
+    for n in nodes(roots) {
+        let indent = "  ".repeat(match n.kind { Kind::Group(_) => n.depth - 1, Kind::Garbage(_) => n.depth } as usize);
+        out += &match n.kind {
+            Kind::Group(_)          => format!("{}group {}..{} depth {} score {}\n", indent, n.start, n.end, n.depth, n.score()),
+            Kind::Garbage(ref s)    => format!("{}garbage {}..{} {:?}\n", indent, n.start, n.end, s),
+        };
+    }
+    out
+}
+
 fn main() {
+    match std::env::args().nth(1).as_deref() {
+        Some("tree")    => {
+            let roots = parse_tree(std::io::stdin());
+            print!("{}", pretty(&roots));
+            if let Some(n) = highest_score(&roots) {
+                println!("highest score {} at {}..{}", n.score(), n.start, n.end);
+            }
+            println!("{:?}", totals(&roots));
+            return
+        },
+        Some("garbage") => {
+            for g in garbage_contents(&parse_tree(std::io::stdin())) {
+                println!("{}", g);
+            }
+            return
+        },
+        Some("check")   => {
+            let (result, errors) = check_groups(std::io::stdin(), Mode::Recover).unwrap();
+            for e in errors {
+                eprintln!("{}", e);
+            }
+            println!("{:?}", result);
+            return
+        },
+        Some("strict")  => {
+            match check_groups(std::io::stdin(), Mode::Strict) {
+                Ok((result, _)) => println!("{:?}", result),
+                Err(e)          => { eprintln!("{}", e); std::process::exit(1) },
+            }
+            return
+        },
+        Some("parallel") => {
+            let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+            println!("{:?}", count_groups_parallel(std::io::stdin(), 8 << 20, threads));
+            return
+        },
+        _               => {},
+    }
     println!("{:?}", count_groups(std::io::stdin()));
 }
 
@@ -54,4 +441,122 @@ fn test_count_groups() {
     assert_eq!(Result{groups: 1, score:1,  garbage: 0}, count_groups(Cursor::new("{<!!!>>}")));
     assert_eq!(Result{groups: 1, score:1,  garbage: 10}, count_groups(Cursor::new(r#"{<{o"i!a,<{i<a>}"#)));
     assert_eq!(Result{groups: 2, score:3,  garbage: 0}, count_groups(Cursor::new("{!!{}}")));
+    assert_eq!(Result{groups: 1, score:1,  garbage: 0}, count_groups(Cursor::new("{>a}")));
+}
+
+#[test]
+fn test_parse_tree() {
+    let roots = parse_tree(Cursor::new("{{<a!>b>},{<!!>}}"));
+    assert_eq!(vec![Node{start: 0, end: 17, depth: 1, kind: Kind::Group(vec![
+        Node{start: 1, end: 9, depth: 2, kind: Kind::Group(vec![
+            Node{start: 2, end: 8, depth: 2, kind: Kind::Garbage("ab".to_owned())},
+        ])},
+        Node{start: 10, end: 16, depth: 2, kind: Kind::Group(vec![
+            Node{start: 11, end: 15, depth: 2, kind: Kind::Garbage("".to_owned())},
+        ])},
+    ])}], roots);
+    assert_eq!(vec!["ab", ""], garbage_contents(&roots));
+    assert_eq!(Some(1), highest_score(&roots).map(|n| n.start));
+    assert_eq!("group 0..17 depth 1 score 1
+  group 1..9 depth 2 score 2
+    garbage 2..8 \"ab\"
+  group 10..16 depth 2 score 2
+    garbage 11..15 \"\"
+", pretty(&roots));
+}
+
+#[test]
+fn test_tree_totals() {
+    for s in &["{}", "{{{}}}", "{{},{}}", "{{{},{},{{}}}}", "{<{},{},{{}}>}", "{<a>,<a>,<a>,<a>}",
+               "{{<a>},{<a>},{<a>},{<a>}}", "{{<!>},{<!>},{<!>},{<a>}}", "{<random characters>}",
+               "{<<<<>}", "{<{!>}>}", "{<!!>}", "{<!!!>>}", r#"{<{o"i!a,<{i<a>}"#, "{!!{}}"] {
+        assert_eq!(count_groups(Cursor::new(s)), totals(&parse_tree(Cursor::new(s))), "{}", s);
+    }
+    let roots = parse_tree(Cursor::new("{{{},{{}}},{{{}}}}"));
+    assert_eq!(Some((4, 6)), highest_score(&roots).map(|n| (n.score(), n.start)));
+    assert_eq!(None, highest_score(&parse_tree(Cursor::new("<{}>"))));
+}
+
+#[test]
+fn test_check_groups() {
+    for s in &["{}", "{{<!>},{<!>},{<!>},{<a>}}", "{<{!>}>}", "{<!!!>>}", r#"{<{o"i!a,<{i<a>}"#, "{!!{}}"] {
+        assert_eq!(Ok((count_groups(Cursor::new(s)), vec![])), check_groups(Cursor::new(s), Mode::Strict));
+    }
+
+    let check = |s, mode| check_groups(Cursor::new(s), mode);
+    assert_eq!(Ok((Result{groups: 2, score: 2, garbage: 0}, vec![StreamError::UnmatchedClose(2), StreamError::StrayGarbageEnd(4)])),
+               check("{}}{>}", Mode::Recover));
+    assert_eq!(Err(StreamError::UnmatchedClose(2)), check("{}}{>}", Mode::Strict));
+    assert_eq!(Ok((Result{groups: 3, score: 3, garbage: 2}, vec![
+        StreamError::UnclosedGroup(0),
+        StreamError::UnclosedGroup(1),
+        StreamError::UnterminatedGarbage(4),
+        StreamError::TrailingCancel(9),
+    ])), check("{{{}<ab!>!", Mode::Recover));
+    assert_eq!(Err(StreamError::UnclosedGroup(0)), check("{{{}<ab!>!", Mode::Strict));
+    assert_eq!(Err(StreamError::TrailingCancel(2)), check("{}!", Mode::Strict));
+    assert_eq!("byte 3: '}' without an open group", StreamError::UnmatchedClose(3).to_string());
+
+    for s in streams(7) {
+        let (result, errors) = check_groups(Cursor::new(&s), Mode::Recover).unwrap();
+        let stray: Vec<usize> = errors.iter().filter_map(|e| match *e {
+            StreamError::UnmatchedClose(pos)    => Some(pos),
+            _                                   => None,
+        }).collect();
+        let kept: Vec<u8> = s.iter().enumerate().filter(|&(i, _)| !stray.contains(&i)).map(|(_, &b)| b).collect();
+        assert_eq!(count_groups(Cursor::new(&kept)), result, "{:?}", String::from_utf8_lossy(&s));
+        if errors.is_empty() {
+            assert_eq!(Ok((result, errors)), check_groups(Cursor::new(&s), Mode::Strict));
+        }
+    }
+}
+
+// Every stream of up to `len` bytes over the bytes that mean something,
+// plus one that doesn't.
+#[cfg(test)]
+fn streams(len: usize) -> Vec<Vec<u8>> {
+    let mut all = vec![vec![]];
+    let mut last = vec![vec![]];
+    for _ in 0..len {
+        last = last.iter().flat_map(|s: &Vec<u8>| b"{}<>!a".iter().map(move |&b| {
+            let mut s = s.clone();
+            s.push(b);
+            s
+        })).collect();
+        all.extend(last.iter().cloned());
+    }
+    all
+}
+
+#[test]
+fn test_count_groups_parallel() {
+    let mut cases: Vec<Vec<u8>> = [
+        "{{<!>},{<!>},{<!>},{<a>}}", "{<{!>}>}", "{<!!!>>}", r#"{<{o"i!a,<{i<a>}"#, "{!!{}}",
+        "{{<a!>b>},{<!!>}}", "}}{>{<}>", "!!!!!{<!!!!>}!", "{{{}<ab!>!",
+    ].iter().map(|s| s.as_bytes().to_vec()).collect();
+    cases.extend(streams(5));
+    for s in &cases {
+        let expected = count_groups(Cursor::new(s));
+        for &(chunk, threads) in &[(1, 1), (1, 3), (2, 2), (3, 4), (7, 2), (1000, 2)] {
+            assert_eq!(expected, count_groups_parallel(Cursor::new(s), chunk, threads), "{:?} in chunks of {}", String::from_utf8_lossy(s), chunk);
+        }
+    }
+
+    // Nested deep enough that the score no longer fits in an i32.
+    let deep = format!("{}{}", "{".repeat(70_000), "}".repeat(70_000));
+    let expected = Result{groups: 70_000, score: 70_000 * 70_001 / 2, garbage: 0};
+    assert_eq!(expected, count_groups(Cursor::new(&deep)));
+    assert_eq!(expected, count_groups_parallel(Cursor::new(&deep), 4096, 4));
+}
+
+#[test]
+#[should_panic(expected = "chunks must hold at least one byte")]
+fn test_count_groups_parallel_empty_chunks() {
+    count_groups_parallel(Cursor::new("{}"), 0, 2);
+}
+
+#[test]
+#[should_panic(expected = "at least one thread must count")]
+fn test_count_groups_parallel_no_threads() {
+    count_groups_parallel(Cursor::new("{}"), 4, 0);
 }